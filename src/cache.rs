@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::dictionary::DictionaryProvider;
+use crate::dictionary::DictionarySource;
+use crate::dictionary::Lookup;
+use crate::xdg_dir;
+use crate::Definition;
+
+pub const MAX_ENTRIES: usize = 2000;
+/// How long a word is remembered as not found, in seconds. Dictionaries change rarely, but a
+/// word may have been looked up in a form they do not know yet.
+pub const NOT_FOUND_TTL: u64 = 24 * 60 * 60;
+
+/// Definitions fetched before, stored in `$XDG_CACHE_HOME/booklet/definitions.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DefinitionCache {
+    pub entries: HashMap<String, CacheEntry>,
+    /// Whether entries were used since the cache was saved.
+    #[serde(skip)]
+    pub unsaved: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub cached_at: u64,
+    /// When the entry was last looked up, which decides what is evicted first.
+    #[serde(default)]
+    pub used_at: u64,
+    /// The definition, or `None` if the dictionary does not know the word.
+    pub definition: Option<Definition>,
+}
+
+impl DefinitionCache {
+    pub fn path() -> Option<PathBuf> {
+        let mut path = xdg_dir("XDG_CACHE_HOME", ".cache")?;
+        path.push("booklet");
+        path.push("definitions.json");
+        Some(path)
+    }
+
+    /// Loads the cache, starting over if it is missing or unreadable.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => anyhow::bail!("No cache directory found"),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Removes the cache file, returning the number of definitions it held.
+    pub fn clear() -> anyhow::Result<usize> {
+        let count = Self::load().entries.len();
+        if let Some(path) = Self::path().filter(|path| path.exists()) {
+            fs::remove_file(path)?;
+        }
+        Ok(count)
+    }
+
+    pub fn key(dictionary: &str, word: &str) -> String {
+        format!("{dictionary}:{}", normalize_word(word))
+    }
+
+    /// Returns the cached result of looking up a word: `Some(None)` if the word was not found
+    /// recently. Marks the entry as used.
+    pub fn get(&mut self, dictionary: &str, word: &str) -> Option<Option<Definition>> {
+        let key = Self::key(dictionary, word);
+        let now = now();
+        let entry = self.entries.get_mut(&key)?;
+        if entry.definition.is_none() && now.saturating_sub(entry.cached_at) > NOT_FOUND_TTL {
+            self.entries.remove(&key);
+            return None;
+        }
+        entry.used_at = now;
+        self.unsaved = true;
+        Some(entry.definition.clone())
+    }
+
+    /// Adds the result of a lookup, evicting the least recently used entries once the cache is
+    /// full.
+    pub fn insert(&mut self, dictionary: &str, word: &str, definition: Option<Definition>) {
+        let now = now();
+        self.entries.insert(
+            Self::key(dictionary, word),
+            CacheEntry {
+                cached_at: now,
+                used_at: now,
+                definition,
+            },
+        );
+        if self.entries.len() > MAX_ENTRIES {
+            let mut entries = self
+                .entries
+                .iter()
+                .map(|(key, entry)| (entry.used_at.max(entry.cached_at), key.to_string()))
+                .collect::<Vec<_>>();
+            entries.sort();
+            for (_, key) in entries.iter().take(self.entries.len() - MAX_ENTRIES) {
+                self.entries.remove(key);
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Lowercases a word and strips the punctuation around it.
+pub fn normalize_word(word: &str) -> String {
+    word.trim_matches(|char: char| !char.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Answers lookups from the definition cache before asking the dictionary.
+#[derive(Debug)]
+pub struct CachedProvider {
+    pub id: String,
+    pub provider: Arc<dyn DictionaryProvider>,
+    pub cache: Mutex<DefinitionCache>,
+}
+
+impl CachedProvider {
+    pub fn new(source: &DictionarySource) -> Self {
+        Self {
+            id: source.id(),
+            provider: source.open(),
+            cache: Mutex::new(DefinitionCache::load()),
+        }
+    }
+}
+
+impl DictionaryProvider for CachedProvider {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    fn define<'a>(&'a self, word: &'a str) -> Lookup<'a> {
+        Box::pin(async move {
+            {
+                let mut cache = self.cache.lock().unwrap();
+                // the time it was used is saved with the next new word or on exit
+                if let Some(definition) = cache.get(&self.id, word) {
+                    return Ok(definition);
+                }
+            }
+            let definition = self.provider.define(word).await?;
+            let mut cache = self.cache.lock().unwrap();
+            cache.insert(&self.id, word, definition.clone());
+            // a cache that cannot be written only costs another lookup later
+            let _ = cache.save();
+            Ok(definition)
+        })
+    }
+}
+
+impl Drop for CachedProvider {
+    fn drop(&mut self) {
+        if let Ok(cache) = self.cache.get_mut() {
+            if cache.unsaved {
+                // a cache that cannot be written only forgets when it was used
+                let _ = cache.save();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(word: &str) -> Definition {
+        Definition {
+            word: word.to_string(),
+            phonetics: Vec::new(),
+            meanings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = DefinitionCache::default();
+        for i in 0..MAX_ENTRIES {
+            cache.insert("online", &format!("word{i}"), Some(definition("word")));
+        }
+        for entry in cache.entries.values_mut() {
+            entry.cached_at -= 10;
+            entry.used_at -= 10;
+        }
+        assert!(cache.get("online", "word0").is_some());
+        assert!(cache.unsaved);
+        cache.insert("online", "new", None);
+        assert_eq!(cache.entries.len(), MAX_ENTRIES);
+        assert!(cache.get("online", "word0").is_some());
+        assert!(cache.get("online", "new").is_some());
+    }
+
+    #[test]
+    fn forgets_words_not_found_after_a_while() {
+        let mut cache = DefinitionCache::default();
+        cache.insert("online", "old", Some(definition("old")));
+        cache.insert("online", "missing", None);
+        assert!(matches!(cache.get("online", "missing"), Some(None)));
+        for entry in cache.entries.values_mut() {
+            entry.cached_at -= NOT_FOUND_TTL + 1;
+        }
+        assert!(cache.get("online", "missing").is_none());
+        assert!(matches!(cache.get("online", "old"), Some(Some(_))));
+    }
+}
@@ -0,0 +1,271 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::theme::Color;
+
+/// The index of the `colors` capability among the numbers of a terminfo entry.
+const MAX_COLORS: usize = 13;
+const TERMINFO_DIRS: [&str; 4] = [
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+];
+/// The levels of the red, green and blue axes of the 6x6x6 color cube of 256-color terminals.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// How many colors the terminal can show. Colors are downsampled to what it supports, and in
+/// monochrome mode only bold, reverse and underline are used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorDepth {
+    #[default]
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+    Monochrome,
+}
+
+impl ColorDepth {
+    /// Detects the color depth from `NO_COLOR`, `COLORTERM`, `TERM` and the terminfo entry of
+    /// the terminal.
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return ColorDepth::Monochrome;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default().to_lowercase();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" {
+            return ColorDepth::Monochrome;
+        }
+        if term.ends_with("-direct") || term.ends_with("-truecolor") {
+            return ColorDepth::TrueColor;
+        }
+        match terminfo_colors(&term) {
+            Some(colors) => ColorDepth::from_colors(colors),
+            None if term.contains("256color") => ColorDepth::Ansi256,
+            None => ColorDepth::Ansi16,
+        }
+    }
+
+    fn from_colors(colors: u32) -> Self {
+        match colors {
+            0x1000000.. => ColorDepth::TrueColor,
+            256.. => ColorDepth::Ansi256,
+            8.. => ColorDepth::Ansi16,
+            _ => ColorDepth::Monochrome,
+        }
+    }
+}
+
+/// Reads the number of colors from the compiled terminfo entry of a terminal.
+fn terminfo_colors(term: &str) -> Option<u32> {
+    let first = term.chars().next()?;
+    let mut dirs = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(paths) = env::var_os("TERMINFO_DIRS") {
+        dirs.extend(env::split_paths(&paths).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+    dirs.extend(TERMINFO_DIRS.map(PathBuf::from));
+    // entries are grouped by their first letter, or by its hex code on macOS
+    for dir in dirs {
+        for group in [first.to_string(), format!("{:x}", first as u32)] {
+            if let Ok(bytes) = fs::read(dir.join(group).join(term)) {
+                return max_colors(&bytes);
+            }
+        }
+    }
+    None
+}
+
+/// Parses the `colors` number of a compiled terminfo entry, see term(5). Entries without it
+/// describe terminals without colors.
+fn max_colors(bytes: &[u8]) -> Option<u32> {
+    let short = |i: usize| Some(u16::from_le_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]));
+    // the extended format stores numbers as 32 instead of 16 bits
+    let number_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let (names, bools, numbers) = (short(2)? as usize, short(4)? as usize, short(6)?);
+    if numbers as usize <= MAX_COLORS {
+        return Some(0);
+    }
+    let mut start = 12 + names + bools;
+    start += start % 2;
+    let at = start + MAX_COLORS * number_size;
+    let value = match number_size {
+        2 => short(at)? as i16 as i32,
+        _ => i32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?),
+    };
+    Some(u32::try_from(value).unwrap_or_default())
+}
+
+/// Returns the closest color of the 256-color palette, either from the color cube or from the
+/// grayscale ramp.
+pub fn ansi256(Color(r, g, b): Color) -> u8 {
+    let level = |value: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(value))
+            .unwrap_or_default()
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = Color(CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_value = 8 + 10 * gray;
+    if distance(Color(gray_value, gray_value, gray_value), Color(r, g, b))
+        < distance(cube, Color(r, g, b))
+    {
+        232 + gray
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+/// Returns the closest of the 16 basic colors as its index from 0 (black) to 7 (white) and
+/// whether it is the bright variant. Colorful colors are matched by hue, so that dark tints
+/// like the marker backgrounds keep their color instead of turning black.
+pub fn ansi16(Color(r, g, b): Color) -> (u8, bool) {
+    let max = r.max(g).max(b) as i32;
+    let min = r.min(g).min(b) as i32;
+    let lightness = (max + min) / 2;
+    if max - min < 40 {
+        return match lightness {
+            0..=42 => (0, false),
+            43..=127 => (0, true),
+            128..=212 => (7, false),
+            _ => (7, true),
+        };
+    }
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let delta = max - min;
+    let hue = if max == r {
+        (60 * (g - b) / delta).rem_euclid(360)
+    } else if max == g {
+        60 * (b - r) / delta + 120
+    } else {
+        60 * (r - g) / delta + 240
+    };
+    // red, yellow, green, cyan, blue and magenta are 60 degrees apart
+    let index = match hue {
+        30..=89 => 3,
+        90..=149 => 2,
+        150..=209 => 6,
+        210..=269 => 4,
+        270..=329 => 5,
+        _ => 1,
+    };
+    (index, lightness > 160)
+}
+
+fn distance(a: Color, b: Color) -> u32 {
+    let channel = |x: u8, y: u8| (x.abs_diff(y) as u32).pow(2);
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A compiled terminfo entry with the given numbers, in the legacy or extended format.
+    fn terminfo(magic: u16, numbers: &[i32]) -> Vec<u8> {
+        let names = b"test|Test terminal\0";
+        let bools = [1, 0];
+        let mut bytes = Vec::new();
+        for short in [
+            magic,
+            names.len() as u16,
+            bools.len() as u16,
+            numbers.len() as u16,
+            0,
+            0,
+        ] {
+            bytes.extend(short.to_le_bytes());
+        }
+        bytes.extend(names);
+        bytes.extend(bools);
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        for &number in numbers {
+            match magic {
+                0o432 => bytes.extend((number as i16).to_le_bytes()),
+                _ => bytes.extend(number.to_le_bytes()),
+            }
+        }
+        bytes
+    }
+
+    fn numbers(colors: i32) -> Vec<i32> {
+        let mut numbers = vec![-1; MAX_COLORS + 3];
+        numbers[0] = 80;
+        numbers[MAX_COLORS] = colors;
+        numbers
+    }
+
+    #[test]
+    fn reads_colors_from_terminfo() {
+        assert_eq!(max_colors(&terminfo(0o432, &numbers(8))), Some(8));
+        assert_eq!(max_colors(&terminfo(0o432, &numbers(256))), Some(256));
+        assert_eq!(
+            max_colors(&terminfo(0o1036, &numbers(0x1000000))),
+            Some(0x1000000)
+        );
+        // a missing or cancelled capability means no colors
+        assert_eq!(max_colors(&terminfo(0o432, &numbers(-1))), Some(0));
+        assert_eq!(max_colors(&terminfo(0o432, &[80; MAX_COLORS])), Some(0));
+        assert_eq!(max_colors(&terminfo(0o433, &numbers(256))), None);
+        let bytes = terminfo(0o1036, &numbers(256));
+        assert_eq!(max_colors(&bytes[..bytes.len() - 10]), None);
+    }
+
+    #[test]
+    fn maps_colors_to_depth() {
+        assert_eq!(ColorDepth::from_colors(0), ColorDepth::Monochrome);
+        assert_eq!(ColorDepth::from_colors(8), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::from_colors(88), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::from_colors(256), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_colors(0x1000000), ColorDepth::TrueColor);
+    }
+
+    #[test]
+    fn downsamples_to_256_colors() {
+        assert_eq!(ansi256(Color(0, 0, 0)), 16);
+        assert_eq!(ansi256(Color(255, 0, 0)), 196);
+        assert_eq!(ansi256(Color(255, 255, 255)), 231);
+        assert_eq!(ansi256(Color(95, 135, 175)), 67);
+        // grays between the levels of the cube go to the grayscale ramp
+        assert_eq!(ansi256(Color(128, 128, 128)), 244);
+        assert_eq!(ansi256(Color(30, 30, 32)), 234);
+    }
+
+    #[test]
+    fn downsamples_to_16_colors() {
+        assert_eq!(ansi16(Color(0, 0, 0)), (0, false));
+        assert_eq!(ansi16(Color(100, 100, 100)), (0, true));
+        assert_eq!(ansi16(Color(200, 200, 200)), (7, false));
+        assert_eq!(ansi16(Color(255, 255, 255)), (7, true));
+        assert_eq!(ansi16(Color(255, 0, 0)), (1, false));
+        assert_eq!(ansi16(Color(150, 255, 255)), (6, true));
+        // dark tints keep their hue
+        assert_eq!(ansi16(Color(60, 40, 0)), (3, false));
+        assert_eq!(ansi16(Color(20, 30, 90)), (4, false));
+        assert_eq!(ansi16(Color(0, 70, 20)), (2, false));
+    }
+}
@@ -0,0 +1,773 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use serde::Serialize;
+
+const ONLINE_URL: &str = "https://api.dictionaryapi.dev/api/v2/entries/en";
+const DICT_BASE64: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Definition {
+    pub word: String,
+    pub phonetics: Vec<String>,
+    pub meanings: Vec<Meaning>,
+}
+
+/// The senses of a word for one part of speech.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Meaning {
+    pub part_of_speech: Option<String>,
+    pub senses: Vec<Sense>,
+    pub synonyms: Vec<String>,
+    pub antonyms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Sense {
+    pub definition: String,
+    pub example: Option<String>,
+    pub synonyms: Vec<String>,
+    pub antonyms: Vec<String>,
+}
+
+impl Definition {
+    /// Parses a response of the free dictionary API, merging the meanings of all entries
+    /// by part of speech.
+    pub fn from_json(value: &serde_json::Value) -> Option<Definition> {
+        let entries = value.as_array()?;
+        let word = entries.first()?.get("word")?.as_str()?;
+        let mut phonetics = Vec::new();
+        let mut meanings: Vec<Meaning> = Vec::new();
+        for entry in entries {
+            let texts = entry
+                .get("phonetics")
+                .and_then(|phonetics| phonetics.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|phonetic| phonetic.get("text"));
+            for text in entry.get("phonetic").into_iter().chain(texts) {
+                if let Some(text) = text.as_str().filter(|text| !text.is_empty()) {
+                    if !phonetics.iter().any(|phonetic| phonetic == text) {
+                        phonetics.push(text.to_string());
+                    }
+                }
+            }
+            for meaning in entry.get("meanings")?.as_array()? {
+                let part_of_speech = meaning
+                    .get("partOfSpeech")
+                    .and_then(|part| part.as_str())
+                    .map(|part| part.to_string());
+                let index = match meanings
+                    .iter()
+                    .position(|meaning| meaning.part_of_speech == part_of_speech)
+                {
+                    Some(index) => index,
+                    None => {
+                        meanings.push(Meaning {
+                            part_of_speech,
+                            ..Default::default()
+                        });
+                        meanings.len() - 1
+                    }
+                };
+                let target = &mut meanings[index];
+                for definition in meaning.get("definitions")?.as_array()? {
+                    target.senses.push(Sense {
+                        definition: definition.get("definition")?.as_str()?.to_string(),
+                        example: definition
+                            .get("example")
+                            .and_then(|example| example.as_str())
+                            .map(|example| example.to_string()),
+                        synonyms: string_list(definition.get("synonyms")),
+                        antonyms: string_list(definition.get("antonyms")),
+                    });
+                }
+                extend_unique(&mut target.synonyms, string_list(meaning.get("synonyms")));
+                extend_unique(&mut target.antonyms, string_list(meaning.get("antonyms")));
+            }
+        }
+        Some(Definition {
+            word: word.to_string(),
+            phonetics,
+            meanings,
+        })
+    }
+
+    /// Creates a definition from the plain text of a dictionary entry, taking each paragraph
+    /// as one sense. A first line repeating the headword is skipped.
+    pub fn from_text(word: &str, text: &str) -> Option<Definition> {
+        let mut lines = text.lines().peekable();
+        if lines
+            .peek()
+            .is_some_and(|line| line.trim().eq_ignore_ascii_case(word))
+        {
+            lines.next();
+        }
+        let mut senses = Vec::new();
+        let mut item = Vec::new();
+        for line in lines {
+            if line.trim().is_empty() {
+                if !item.is_empty() {
+                    senses.push(Sense {
+                        definition: item.join(" "),
+                        ..Default::default()
+                    });
+                    item.clear();
+                }
+                continue;
+            }
+            item.extend(line.split_whitespace());
+        }
+        if !item.is_empty() {
+            senses.push(Sense {
+                definition: item.join(" "),
+                ..Default::default()
+            });
+        }
+        if senses.is_empty() {
+            return None;
+        }
+        Some(Definition {
+            word: word.to_string(),
+            phonetics: Vec::new(),
+            meanings: vec![Meaning {
+                senses,
+                ..Default::default()
+            }],
+        })
+    }
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.word)?;
+        if !self.phonetics.is_empty() {
+            write!(f, "  {}", self.phonetics.join(", "))?;
+        }
+        writeln!(f)?;
+        for meaning in &self.meanings {
+            writeln!(f)?;
+            if let Some(part_of_speech) = &meaning.part_of_speech {
+                writeln!(f, "{part_of_speech}")?;
+            }
+            for (i, sense) in meaning.senses.iter().enumerate() {
+                writeln!(f, "  {}. {}", i + 1, sense.definition)?;
+                if let Some(example) = &sense.example {
+                    writeln!(f, "     \"{example}\"")?;
+                }
+                if !sense.synonyms.is_empty() {
+                    writeln!(f, "     synonyms: {}", sense.synonyms.join(", "))?;
+                }
+                if !sense.antonyms.is_empty() {
+                    writeln!(f, "     antonyms: {}", sense.antonyms.join(", "))?;
+                }
+            }
+            if !meaning.synonyms.is_empty() {
+                writeln!(f, "  synonyms: {}", meaning.synonyms.join(", "))?;
+            }
+            if !meaning.antonyms.is_empty() {
+                writeln!(f, "  antonyms: {}", meaning.antonyms.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn string_list(value: Option<&serde_json::Value>) -> Vec<String> {
+    value
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.as_str())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+fn extend_unique(list: &mut Vec<String>, items: Vec<String>) {
+    for item in items {
+        if !list.contains(&item) {
+            list.push(item);
+        }
+    }
+}
+
+pub type Lookup<'a> = Pin<Box<dyn Future<Output = anyhow::Result<Option<Definition>>> + Send + 'a>>;
+
+/// A source of word definitions.
+pub trait DictionaryProvider: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn define<'a>(&'a self, word: &'a str) -> Lookup<'a>;
+}
+
+/// Selects the dictionary used for definitions.
+///
+/// ```toml
+/// [dictionary]
+/// provider = "stardict"
+/// path = "/usr/share/stardict/dic/wordnet"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum DictionarySource {
+    #[default]
+    Online,
+    StarDict {
+        path: String,
+    },
+    Dict {
+        path: String,
+    },
+}
+
+impl DictionarySource {
+    pub fn open(&self) -> Arc<dyn DictionaryProvider> {
+        match self {
+            DictionarySource::Online => Arc::new(Online),
+            DictionarySource::StarDict { path } => Arc::new(StarDict::new(path)),
+            DictionarySource::Dict { path } => Arc::new(Dict::new(path)),
+        }
+    }
+
+    /// Identifies the dictionary in the definition cache.
+    pub fn id(&self) -> String {
+        match self {
+            DictionarySource::Online => "online".to_string(),
+            DictionarySource::StarDict { path } => format!("stardict:{path}"),
+            DictionarySource::Dict { path } => format!("dict:{path}"),
+        }
+    }
+}
+
+/// Looks up words using the free dictionary API.
+#[derive(Debug)]
+pub struct Online;
+
+impl Online {
+    /// Returns the address of the entries of a word, which goes into the path as one encoded
+    /// segment.
+    fn url(word: &str) -> reqwest::Url {
+        let mut url = reqwest::Url::parse(ONLINE_URL).unwrap();
+        url.path_segments_mut().unwrap().push(word);
+        url
+    }
+}
+
+impl DictionaryProvider for Online {
+    fn name(&self) -> &str {
+        "online"
+    }
+
+    fn define<'a>(&'a self, word: &'a str) -> Lookup<'a> {
+        Box::pin(async move {
+            let res = reqwest::get(Online::url(word)).await?;
+            let result: serde_json::Value = res.json().await?;
+            Ok(Definition::from_json(&result))
+        })
+    }
+}
+
+/// Entries of an offline dictionary: the lowercased headword mapped to the locations of its
+/// articles in the uncompressed dictionary data.
+#[derive(Debug, Default)]
+struct Index {
+    entries: HashMap<String, Vec<(usize, usize)>>,
+    data: Vec<u8>,
+    /// The `sametypesequence` of a StarDict dictionary.
+    types: Option<String>,
+}
+
+/// The index of an offline dictionary, read when it is first needed.
+type LazyIndex = Arc<OnceLock<Arc<Index>>>;
+
+/// Returns the index of a dictionary, reading it on a blocking thread the first time, as
+/// reading and decompressing the whole dictionary takes a while. The index is kept even if
+/// the lookup waiting for it is cancelled.
+async fn load_index(
+    cell: &LazyIndex,
+    base: &Path,
+    read: fn(&Path) -> anyhow::Result<Index>,
+) -> anyhow::Result<Arc<Index>> {
+    if let Some(index) = cell.get() {
+        return Ok(Arc::clone(index));
+    }
+    let cell = Arc::clone(cell);
+    let base = base.to_path_buf();
+    async_std::task::spawn_blocking(move || {
+        let index = Arc::new(read(&base)?);
+        Ok(Arc::clone(cell.get_or_init(|| index)))
+    })
+    .await
+}
+
+impl Index {
+    fn articles(&self, word: &str) -> Vec<&[u8]> {
+        self.entries
+            .get(&word.to_lowercase())
+            .map(|locations| {
+                locations
+                    .iter()
+                    .filter_map(|(offset, size)| self.data.get(*offset..offset + size))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Reads a dictionary in the StarDict format: `.ifo`, `.idx` and `.dict` or `.dict.dz`.
+#[derive(Debug)]
+pub struct StarDict {
+    base: PathBuf,
+    index: LazyIndex,
+}
+
+impl StarDict {
+    pub fn new(path: &str) -> Self {
+        Self {
+            base: base_path(path, &[".ifo", ".idx", ".dict.dz", ".dict"]),
+            index: LazyIndex::default(),
+        }
+    }
+
+    fn read_index(base: &Path) -> anyhow::Result<Index> {
+        let ifo = read_file(&with_extension(base, ".ifo"))?;
+        let ifo = String::from_utf8_lossy(&ifo);
+        let mut offset_bits = 32;
+        let mut types = None;
+        for line in ifo.lines() {
+            match line.split_once('=') {
+                Some(("idxoffsetbits", value)) => offset_bits = value.trim().parse()?,
+                Some(("sametypesequence", value)) => types = Some(value.trim().to_string()),
+                _ => (),
+            }
+        }
+        let idx = read_file(&with_extension(base, ".idx"))?;
+        let offset_size = if offset_bits == 64 { 8 } else { 4 };
+        let mut entries = HashMap::<String, Vec<(usize, usize)>>::new();
+        let mut rest = &idx[..];
+        while let Some(end) = rest.iter().position(|byte| byte == &0) {
+            let word = String::from_utf8_lossy(&rest[..end]).to_lowercase();
+            let fields = match rest.get(end + 1..end + 1 + offset_size + 4) {
+                Some(fields) => fields,
+                None => break,
+            };
+            let offset = fields[..offset_size]
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as usize);
+            let size = fields[offset_size..]
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as usize);
+            entries.entry(word).or_default().push((offset, size));
+            rest = &rest[end + 1 + offset_size + 4..];
+        }
+        let data = read_file(&with_extension(base, ".dict"))?;
+        Ok(Index {
+            entries,
+            data,
+            types,
+        })
+    }
+
+    /// Extracts the text fields of an article. Without `sametypesequence` every field starts
+    /// with its type; text fields are terminated by a null byte except for the last one.
+    fn article_text(types: Option<&str>, article: &[u8]) -> String {
+        let mut texts = Vec::new();
+        let mut rest = article;
+        let mut sequence = types.map(|types| types.chars());
+        while !rest.is_empty() {
+            let kind = match &mut sequence {
+                Some(sequence) => match sequence.next() {
+                    Some(kind) => kind,
+                    None => break,
+                },
+                None => {
+                    let kind = rest[0] as char;
+                    rest = &rest[1..];
+                    kind
+                }
+            };
+            if kind.is_ascii_uppercase() {
+                // binary fields are prefixed with their size
+                let size = rest
+                    .get(..4)
+                    .map(|bytes| {
+                        bytes
+                            .iter()
+                            .fold(0, |value, byte| value << 8 | *byte as usize)
+                    })
+                    .unwrap_or(rest.len());
+                rest = rest.get(4 + size..).unwrap_or_default();
+                continue;
+            }
+            let end = rest
+                .iter()
+                .position(|byte| byte == &0)
+                .unwrap_or(rest.len());
+            let text = String::from_utf8_lossy(&rest[..end]).to_string();
+            match kind {
+                'h' | 'g' | 'x' => texts.push(strip_markup(&text)),
+                _ => texts.push(text),
+            }
+            rest = rest.get(end + 1..).unwrap_or_default();
+        }
+        texts.join("\n\n")
+    }
+}
+
+impl DictionaryProvider for StarDict {
+    fn name(&self) -> &str {
+        "stardict"
+    }
+
+    fn define<'a>(&'a self, word: &'a str) -> Lookup<'a> {
+        Box::pin(async move {
+            let index = load_index(&self.index, &self.base, StarDict::read_index).await?;
+            let text = index
+                .articles(word)
+                .into_iter()
+                .map(|article| StarDict::article_text(index.types.as_deref(), article))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            Ok(Definition::from_text(word, &text))
+        })
+    }
+}
+
+/// Reads a dictionary in the format of the dictd server: `.index` and `.dict` or `.dict.dz`.
+#[derive(Debug)]
+pub struct Dict {
+    base: PathBuf,
+    index: LazyIndex,
+}
+
+impl Dict {
+    pub fn new(path: &str) -> Self {
+        Self {
+            base: base_path(path, &[".index", ".dict.dz", ".dict"]),
+            index: LazyIndex::default(),
+        }
+    }
+
+    fn read_index(base: &Path) -> anyhow::Result<Index> {
+        let content = read_file(&with_extension(base, ".index"))?;
+        let content = String::from_utf8_lossy(&content);
+        let mut entries = HashMap::<String, Vec<(usize, usize)>>::new();
+        for line in content.lines() {
+            let mut fields = line.split('\t');
+            if let (Some(word), Some(offset), Some(size)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                if let (Some(offset), Some(size)) = (decode_base64(offset), decode_base64(size)) {
+                    entries
+                        .entry(word.to_lowercase())
+                        .or_default()
+                        .push((offset, size));
+                }
+            }
+        }
+        let data = read_file(&with_extension(base, ".dict"))?;
+        Ok(Index {
+            entries,
+            data,
+            types: None,
+        })
+    }
+}
+
+impl DictionaryProvider for Dict {
+    fn name(&self) -> &str {
+        "dict"
+    }
+
+    fn define<'a>(&'a self, word: &'a str) -> Lookup<'a> {
+        Box::pin(async move {
+            let index = load_index(&self.index, &self.base, Dict::read_index).await?;
+            let text = index
+                .articles(word)
+                .into_iter()
+                .map(|article| String::from_utf8_lossy(article).to_string())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            Ok(Definition::from_text(word, &text))
+        })
+    }
+}
+
+/// Strips any of the known extensions from a dictionary path.
+fn base_path(path: &str, extensions: &[&str]) -> PathBuf {
+    for extension in extensions {
+        if let Some(base) = path.strip_suffix(extension) {
+            return PathBuf::from(base);
+        }
+    }
+    PathBuf::from(path)
+}
+
+fn with_extension(base: &Path, extension: &str) -> PathBuf {
+    let mut path = base.as_os_str().to_os_string();
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// Reads a file, falling back to its gzip or dictzip compressed variant.
+fn read_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if path.exists() {
+        return Ok(fs::read(path)?);
+    }
+    for extension in [".dz", ".gz"] {
+        let compressed = with_extension(path, extension);
+        if compressed.exists() {
+            let mut data = Vec::new();
+            GzDecoder::new(fs::File::open(compressed)?).read_to_end(&mut data)?;
+            return Ok(data);
+        }
+    }
+    anyhow::bail!("Dictionary file {} not found", path.display())
+}
+
+fn decode_base64(value: &str) -> Option<usize> {
+    value.chars().try_fold(0, |number, char| {
+        DICT_BASE64.find(char).map(|digit| number * 64 + digit)
+    })
+}
+
+fn strip_markup(text: &str) -> String {
+    let mut plain = String::new();
+    let mut in_tag = false;
+    let mut tag = String::new();
+    for char in text.chars() {
+        match char {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').to_lowercase();
+                if name.starts_with("br") || name.starts_with("p") || name.starts_with("div") {
+                    plain.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(char),
+            _ => plain.push(char),
+        }
+    }
+    plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    /// Writes the files of a dictionary into a fresh directory and returns its base path.
+    fn write_dictionary(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("booklet-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (extension, content) in files {
+            fs::write(dir.join(format!("words{extension}")), content).unwrap();
+        }
+        dir.join("words")
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn encodes_the_word_as_one_path_segment() {
+        assert_eq!(
+            Online::url("running").as_str(),
+            "https://api.dictionaryapi.dev/api/v2/entries/en/running"
+        );
+        assert_eq!(
+            Online::url("what? a/b #c").as_str(),
+            "https://api.dictionaryapi.dev/api/v2/entries/en/what%3F%20a%2Fb%20%23c"
+        );
+        assert_eq!(
+            Online::url("café").as_str(),
+            "https://api.dictionaryapi.dev/api/v2/entries/en/caf%C3%A9"
+        );
+    }
+
+    #[test]
+    fn parses_online_entries() {
+        let response = serde_json::json!([
+            {
+                "word": "run",
+                "phonetic": "/ɹʌn/",
+                "phonetics": [{"text": "/ɹʌn/"}, {"text": "/ɹʊn/", "audio": ""}, {"audio": ""}],
+                "meanings": [
+                    {
+                        "partOfSpeech": "verb",
+                        "definitions": [
+                            {
+                                "definition": "To move swiftly.",
+                                "example": "She ran to the door.",
+                                "synonyms": ["sprint"],
+                                "antonyms": []
+                            },
+                            {"definition": "To flee."}
+                        ],
+                        "synonyms": ["dash", "race"],
+                        "antonyms": ["walk"]
+                    },
+                    {
+                        "partOfSpeech": "noun",
+                        "definitions": [{"definition": "An act of running."}],
+                        "synonyms": [],
+                        "antonyms": []
+                    }
+                ]
+            },
+            {
+                "word": "run",
+                "phonetics": [],
+                "meanings": [
+                    {
+                        "partOfSpeech": "verb",
+                        "definitions": [{"definition": "To operate."}],
+                        "synonyms": ["race", "operate"]
+                    }
+                ]
+            }
+        ]);
+        let definition = Definition::from_json(&response).unwrap();
+        assert_eq!(definition.word, "run");
+        assert_eq!(definition.phonetics, ["/ɹʌn/", "/ɹʊn/"]);
+        let parts = definition
+            .meanings
+            .iter()
+            .map(|meaning| meaning.part_of_speech.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(parts, [Some("verb"), Some("noun")]);
+        let verb = &definition.meanings[0];
+        let senses = verb
+            .senses
+            .iter()
+            .map(|sense| sense.definition.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(senses, ["To move swiftly.", "To flee.", "To operate."]);
+        assert_eq!(
+            verb.senses[0].example.as_deref(),
+            Some("She ran to the door.")
+        );
+        assert_eq!(verb.senses[0].synonyms, ["sprint"]);
+        assert_eq!(verb.synonyms, ["dash", "race", "operate"]);
+        assert_eq!(verb.antonyms, ["walk"]);
+    }
+
+    #[test]
+    fn parses_online_not_found_as_none() {
+        let response = serde_json::json!({
+            "title": "No Definitions Found",
+            "message": "Sorry pal, we couldn't find definitions for the word you were looking for."
+        });
+        assert!(Definition::from_json(&response).is_none());
+    }
+
+    #[test]
+    fn decodes_dictd_base64() {
+        assert_eq!(decode_base64("A"), Some(0));
+        assert_eq!(decode_base64("/"), Some(63));
+        assert_eq!(decode_base64("BA"), Some(64));
+        assert_eq!(decode_base64("Bp"), Some(105));
+        assert_eq!(decode_base64("B="), None);
+    }
+
+    #[test]
+    fn extracts_text_fields_of_stardict_articles() {
+        assert_eq!(
+            StarDict::article_text(Some("m"), b"plain text"),
+            "plain text"
+        );
+        assert_eq!(
+            StarDict::article_text(Some("mh"), b"first\0<b>bold</b><br>line"),
+            "first\n\nbold\nline"
+        );
+        // binary fields are skipped by their size
+        let article = b"mfirst\0W\0\0\0\x03abcgsecond &amp; last";
+        assert_eq!(
+            StarDict::article_text(None, article),
+            "first\n\nsecond & last"
+        );
+    }
+
+    #[test]
+    fn reads_stardict_index() {
+        let mut idx = Vec::new();
+        for (word, offset, size) in [("Apple", 0u32, 5u32), ("bear", 5, 6), ("apple", 11, 3)] {
+            idx.extend(word.as_bytes());
+            idx.push(0);
+            idx.extend(offset.to_be_bytes());
+            idx.extend(size.to_be_bytes());
+        }
+        let base = write_dictionary(
+            "stardict",
+            &[
+                (
+                    ".ifo",
+                    b"StarDict's dict ifo file\nversion=2.4.2\nsametypesequence=m\n",
+                ),
+                (".idx", &idx),
+                (".dict.dz", &gzip(b"fruitanimalred")),
+            ],
+        );
+        let index = StarDict::read_index(&base).unwrap();
+        assert_eq!(index.types.as_deref(), Some("m"));
+        assert_eq!(index.articles("APPLE"), [&b"fruit"[..], &b"red"[..]]);
+        assert_eq!(index.articles("bear"), [&b"animal"[..]]);
+        assert!(index.articles("cat").is_empty());
+        let dictionary = StarDict::new(base.to_str().unwrap());
+        let definition = async_std::task::block_on(dictionary.define("apple")).unwrap();
+        assert_eq!(definition.unwrap().meanings[0].senses.len(), 2);
+        fs::remove_dir_all(base.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reads_dictd_index() {
+        let base = write_dictionary(
+            "dictd",
+            &[
+                (".index", b"apple\tA\tV\nBear\tV\tU\nbroken\t?\tA\n"),
+                (".dict", b"apple\nA round fruit.\nbear\nA large animal."),
+            ],
+        );
+        let index = Dict::read_index(&base).unwrap();
+        assert_eq!(index.articles("apple"), [&b"apple\nA round fruit.\n"[..]]);
+        assert_eq!(index.articles("bear"), [&b"bear\nA large animal."[..]]);
+        assert!(index.articles("broken").is_empty());
+        let dictionary = Dict::new(&format!("{}.index", base.display()));
+        let definition = async_std::task::block_on(dictionary.define("apple"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            definition.meanings[0].senses[0].definition,
+            "A round fruit."
+        );
+        fs::remove_dir_all(base.parent().unwrap()).unwrap();
+    }
+}
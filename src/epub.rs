@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use zip::ZipArchive;
+
+use crate::Codes;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+#[derive(Debug)]
+enum Token {
+    Open {
+        name: String,
+        attributes: HashMap<String, String>,
+        closed: bool,
+    },
+    Close(String),
+    Text(String),
+}
+
+impl Token {
+    fn attribute(&self, key: &str) -> Option<&str> {
+        match self {
+            Token::Open { attributes, .. } => attributes.get(key).map(|value| value.as_str()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Epub {
+    pub content: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Spine items that could not be read and were left out of the content.
+    pub warnings: Vec<String>,
+}
+
+pub fn read(path: &str) -> anyhow::Result<Epub> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)?;
+    let container = read_entry(&mut archive, CONTAINER_PATH)?;
+    let opf_path = tokenize(&container)
+        .iter()
+        .find_map(|token| match token {
+            Token::Open { name, .. } if name == "rootfile" => token.attribute("full-path"),
+            _ => None,
+        })
+        .map(|path| path.to_string())
+        .ok_or_else(|| anyhow::anyhow!("No rootfile found in {CONTAINER_PATH}"))?;
+    let opf = read_entry(&mut archive, &opf_path)?;
+    let mut epub = Epub::default();
+    let mut manifest = HashMap::new();
+    let mut spine = Vec::new();
+    let tokens = tokenize(&opf);
+    for (i, token) in tokens.iter().enumerate() {
+        if let Token::Open { name, .. } = token {
+            match name.as_str() {
+                "title" | "creator" => {
+                    let value = match tokens.get(i + 1) {
+                        Some(Token::Text(text)) => text.trim().to_string(),
+                        _ => continue,
+                    };
+                    if name == "title" && epub.title.is_none() {
+                        epub.title = Some(value);
+                    } else if name == "creator" && epub.author.is_none() {
+                        epub.author = Some(value);
+                    }
+                }
+                "item" => {
+                    if let (Some(id), Some(href)) = (token.attribute("id"), token.attribute("href"))
+                    {
+                        manifest.insert(id.to_string(), href.to_string());
+                    }
+                }
+                "itemref" => {
+                    if token.attribute("linear") == Some("no") {
+                        continue;
+                    }
+                    if let Some(idref) = token.attribute("idref") {
+                        spine.push(idref.to_string());
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    let mut lines = Vec::new();
+    for idref in spine {
+        let href = match manifest.get(&idref) {
+            Some(href) => href,
+            None => {
+                epub.warnings
+                    .push(format!("{idref} is not in the manifest"));
+                continue;
+            }
+        };
+        let entry_path = resolve_path(&opf_path, href);
+        let xhtml = match read_entry(&mut archive, &entry_path) {
+            Ok(xhtml) => xhtml,
+            Err(err) => {
+                epub.warnings.push(format!("{entry_path}: {err}"));
+                continue;
+            }
+        };
+        let mut writer = Writer::default();
+        writer.write(&tokenize(&xhtml));
+        lines.extend(writer.finish());
+    }
+    epub.content = lines.join("\n");
+    Ok(epub)
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, path: &str) -> anyhow::Result<String> {
+    let mut entry = archive.by_name(path)?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Resolves an href from the package document against the directory of the package document.
+fn resolve_path(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let href = decode_percent(href);
+    let mut parts = base.split('/').collect::<Vec<_>>();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn decode_percent(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn tokenize(xml: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = xml;
+    while !rest.is_empty() {
+        let start = match rest.find('<') {
+            Some(start) => start,
+            None => {
+                tokens.push(Token::Text(decode_entities(rest)));
+                break;
+            }
+        };
+        if start > 0 {
+            tokens.push(Token::Text(decode_entities(&rest[..start])));
+        }
+        rest = &rest[start..];
+        if let Some(body) = rest.strip_prefix("<!--") {
+            rest = body
+                .find("-->")
+                .map(|end| &body[end + 3..])
+                .unwrap_or_default();
+            continue;
+        }
+        if let Some(body) = rest.strip_prefix("<![CDATA[") {
+            let end = body.find("]]>").unwrap_or(body.len());
+            tokens.push(Token::Text(body[..end].to_string()));
+            rest = body.get(end + 3..).unwrap_or_default();
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(local_name(name.trim())));
+            continue;
+        }
+        let closed = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        tokens.push(Token::Open {
+            name: local_name(&tag[..name_end]),
+            attributes: parse_attributes(&tag[name_end..]),
+            closed,
+        });
+    }
+    tokens
+}
+
+fn local_name(name: &str) -> String {
+    let name = name.rsplit(':').next().unwrap_or(name);
+    name.to_lowercase()
+}
+
+fn parse_attributes(source: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = source.trim_start();
+    while let Some(equals) = rest.find('=') {
+        let key = local_name(rest[..equals].trim());
+        let value = rest[equals + 1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => break,
+        };
+        let value = &value[1..];
+        let end = value.find(quote).unwrap_or(value.len());
+        attributes.insert(key, decode_entities(&value[..end]));
+        rest = value.get(end + 1..).unwrap_or_default().trim_start();
+    }
+    attributes
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let value = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            "shy" => Some('\u{AD}'),
+            "laquo" => Some('«'),
+            "raquo" => Some('»'),
+            "middot" => Some('·'),
+            "deg" => Some('°'),
+            "pound" => Some('£'),
+            "copy" => Some('©'),
+            "times" => Some('×'),
+            "szlig" => Some('ß'),
+            "aacute" => Some('á'),
+            "agrave" => Some('à'),
+            "acirc" => Some('â'),
+            "auml" => Some('ä'),
+            "ccedil" => Some('ç'),
+            "eacute" => Some('é'),
+            "egrave" => Some('è'),
+            "ecirc" => Some('ê'),
+            "euml" => Some('ë'),
+            "iacute" => Some('í'),
+            "icirc" => Some('î'),
+            "iuml" => Some('ï'),
+            "ntilde" => Some('ñ'),
+            "oacute" => Some('ó'),
+            "ocirc" => Some('ô'),
+            "ouml" => Some('ö'),
+            "uacute" => Some('ú'),
+            "ucirc" => Some('û'),
+            "uuml" => Some('ü'),
+            _ => {
+                if let Some(hex) = entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse::<u32>().ok().and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+        };
+        match value {
+            Some('\u{AD}') => (),
+            Some(char) => decoded.push(char),
+            None => decoded.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Converts XHTML tokens into plain lines, translating inline styles into [`Codes`].
+///
+/// Styles are closed at the end of every line and reopened on the next one, the same way
+/// `Book::highlight_italic` treats italic text spanning multiple lines.
+#[derive(Debug, Default)]
+struct Writer {
+    lines: Vec<String>,
+    line: String,
+    skip: usize,
+    preformatted: usize,
+    italic: usize,
+    bold: usize,
+    italic_applied: bool,
+    bold_applied: bool,
+    pending_space: bool,
+}
+
+impl Writer {
+    fn write(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            match token {
+                Token::Open { name, closed, .. } => {
+                    if *closed {
+                        if name == "br" {
+                            self.break_line();
+                        } else if is_block(name) {
+                            self.break_paragraph();
+                        }
+                        continue;
+                    }
+                    match name.as_str() {
+                        "head" | "script" | "style" | "title" => self.skip += 1,
+                        "i" | "em" | "cite" | "var" | "dfn" => self.italic += 1,
+                        "b" | "strong" => self.bold += 1,
+                        "pre" => {
+                            self.break_paragraph();
+                            self.preformatted += 1;
+                        }
+                        name if is_block(name) => self.break_paragraph(),
+                        _ => (),
+                    }
+                }
+                Token::Close(name) => match name.as_str() {
+                    "head" | "script" | "style" | "title" => {
+                        self.skip = self.skip.saturating_sub(1)
+                    }
+                    "i" | "em" | "cite" | "var" | "dfn" => {
+                        self.italic = self.italic.saturating_sub(1)
+                    }
+                    "b" | "strong" => self.bold = self.bold.saturating_sub(1),
+                    "pre" => {
+                        self.break_paragraph();
+                        self.preformatted = self.preformatted.saturating_sub(1);
+                    }
+                    name if is_block(name) => self.break_paragraph(),
+                    _ => (),
+                },
+                Token::Text(text) => {
+                    if self.skip == 0 {
+                        self.write_text(text);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_text(&mut self, text: &str) {
+        for char in text.chars() {
+            if self.preformatted > 0 {
+                if char == '\n' {
+                    self.break_line();
+                } else {
+                    self.push(char);
+                }
+                continue;
+            }
+            if char.is_whitespace() {
+                self.pending_space = !self.line.is_empty();
+                continue;
+            }
+            if self.pending_space {
+                // styles ending before the space leave it unstyled
+                self.close_styles();
+                self.line.push(' ');
+                self.pending_space = false;
+            }
+            self.push(char);
+        }
+    }
+
+    fn push(&mut self, char: char) {
+        let italic = self.italic > 0;
+        if italic != self.italic_applied {
+            self.line.push(match italic {
+                true => Codes::ITALIC,
+                false => Codes::RESET_ITALIC,
+            });
+            self.italic_applied = italic;
+        }
+        let bold = self.bold > 0;
+        if bold != self.bold_applied {
+            self.line.push(match bold {
+                true => Codes::BOLD,
+                false => Codes::RESET_BOLD,
+            });
+            self.bold_applied = bold;
+        }
+        self.line.push(char);
+    }
+
+    fn close_styles(&mut self) {
+        if self.italic == 0 && self.italic_applied {
+            self.line.push(Codes::RESET_ITALIC);
+            self.italic_applied = false;
+        }
+        if self.bold == 0 && self.bold_applied {
+            self.line.push(Codes::RESET_BOLD);
+            self.bold_applied = false;
+        }
+    }
+
+    fn break_line(&mut self) {
+        if self.italic_applied {
+            self.line.push(Codes::RESET_ITALIC);
+            self.italic_applied = false;
+        }
+        if self.bold_applied {
+            self.line.push(Codes::RESET_BOLD);
+            self.bold_applied = false;
+        }
+        self.lines.push(self.line.trim_end().to_string());
+        self.line.clear();
+        self.pending_space = false;
+    }
+
+    fn break_paragraph(&mut self) {
+        if !self.line.is_empty() {
+            self.break_line();
+        }
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.break_paragraph();
+        self.lines
+    }
+}
+
+fn is_block(name: &str) -> bool {
+    matches!(
+        name,
+        "p" | "div"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "li"
+            | "ul"
+            | "ol"
+            | "dl"
+            | "dt"
+            | "dd"
+            | "blockquote"
+            | "table"
+            | "tr"
+            | "hr"
+            | "section"
+            | "article"
+            | "header"
+            | "footer"
+            | "figure"
+            | "figcaption"
+            | "body"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    fn convert(xhtml: &str) -> Vec<String> {
+        let mut writer = Writer::default();
+        writer.write(&tokenize(xhtml));
+        writer.finish()
+    }
+
+    #[test]
+    fn converts_inline_styles_to_codes() {
+        let lines = convert(
+            "<html><head><title>Skipped</title></head><body>\
+             <p>An <i>italic</i> and <strong>bold</strong> word.</p>\
+             <p><em>Both <b>at once</b></em> &amp; more<br/>next</p></body></html>",
+        );
+        let italic = |text: &str| format!("{}{text}{}", Codes::ITALIC, Codes::RESET_ITALIC);
+        let bold = |text: &str| format!("{}{text}{}", Codes::BOLD, Codes::RESET_BOLD);
+        assert_eq!(
+            lines,
+            [
+                format!("An {} and {} word.", italic("italic"), bold("bold")),
+                String::new(),
+                format!(
+                    "{}Both {}at once{}{} & more",
+                    Codes::ITALIC,
+                    Codes::BOLD,
+                    Codes::RESET_ITALIC,
+                    Codes::RESET_BOLD
+                ),
+                "next".to_string(),
+                String::new(),
+            ]
+        );
+    }
+
+    #[test]
+    fn closes_styles_at_line_ends() {
+        let lines = convert("<p><i>first<br/>second</i></p>");
+        assert_eq!(
+            lines[0],
+            format!("{}first{}", Codes::ITALIC, Codes::RESET_ITALIC)
+        );
+        assert_eq!(
+            lines[1],
+            format!("{}second{}", Codes::ITALIC, Codes::RESET_ITALIC)
+        );
+    }
+
+    #[test]
+    fn skips_spine_items_that_cannot_be_read() {
+        let path = env::temp_dir().join(format!("booklet-epub-{}.epub", std::process::id()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        let entries = [
+            (
+                CONTAINER_PATH,
+                r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<package><manifest>
+                <item id="one" href="one.xhtml"/>
+                <item id="gone" href="gone.xhtml"/>
+                <item id="two" href="two.xhtml"/>
+                </manifest><spine>
+                <itemref idref="one"/><itemref idref="unlisted"/>
+                <itemref idref="gone"/><itemref idref="two"/>
+                </spine></package>"#,
+            ),
+            ("OEBPS/one.xhtml", "<html><body><p>First</p></body></html>"),
+            ("OEBPS/two.xhtml", "<html><body><p>Second</p></body></html>"),
+        ];
+        for (name, content) in entries {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        let epub = read(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        let epub = epub.unwrap();
+        assert!(epub.content.contains("First"));
+        assert!(epub.content.contains("Second"));
+        assert_eq!(epub.warnings.len(), 2);
+        assert!(epub.warnings[0].contains("unlisted"));
+        assert!(epub.warnings[1].contains("gone.xhtml"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::Book;
+use crate::Config;
+use crate::MarkerColor;
+
+const CONTEXT_LENGTH: usize = 120;
+
+#[derive(Debug, Serialize)]
+pub struct Export {
+    pub title: String,
+    pub author: Option<String>,
+    pub exported_at: u64,
+    /// The line width the line numbers were counted at, since they change with reflowing.
+    pub line_width: usize,
+    pub bookmarks: Vec<ExportedBookmark>,
+    pub highlights: Vec<ExportedHighlight>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedBookmark {
+    pub line: usize,
+    pub chapter: Option<String>,
+    pub text: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedHighlight {
+    pub line: usize,
+    pub chapter: Option<String>,
+    pub color: MarkerColor,
+    pub text: String,
+    pub note: Option<String>,
+    pub before: String,
+    pub after: String,
+}
+
+impl Export {
+    /// Resolves the bookmarks and markers of the config back to the text of the book.
+    pub fn new(book: &Book, config: &Config) -> anyhow::Result<Self> {
+        let chapter = |line: usize| {
+            book.chapter_at(line)
+                .and_then(|index| book.toc.get(index))
+                .map(|(title, _)| title.to_string())
+        };
+        // the text around a range, cut back to whole words
+        let context = |offset: usize, length: usize| {
+            let start = offset.saturating_sub(CONTEXT_LENGTH);
+            let before = book.text_at(start, offset - start);
+            let after = book.text_at(offset + length, CONTEXT_LENGTH);
+            (
+                trim_start_to_word(&before, start > 0),
+                trim_end_to_word(&after, offset + length + CONTEXT_LENGTH < book.text.len()),
+            )
+        };
+        let bookmarks = config
+            .bookmarks
+            .iter()
+            .map(|bookmark| {
+                let line = book.line_at(bookmark.offset);
+                let end = book
+                    .line_offsets
+                    .get(line + 1)
+                    .copied()
+                    .unwrap_or(book.text.len());
+                let length = end.saturating_sub(bookmark.offset);
+                let (before, after) = context(bookmark.offset, length);
+                ExportedBookmark {
+                    line,
+                    chapter: chapter(line),
+                    text: book.text_at(bookmark.offset, length),
+                    before,
+                    after,
+                }
+            })
+            .collect();
+        let highlights = config
+            .markers
+            .iter()
+            .map(|marker| {
+                let offset = marker.anchor.offset;
+                let line = book.line_at(offset);
+                let (before, after) = context(offset, marker.length);
+                ExportedHighlight {
+                    line,
+                    chapter: chapter(line),
+                    color: marker.color,
+                    text: book.text_at(offset, marker.length),
+                    note: marker.note.clone(),
+                    before,
+                    after,
+                }
+            })
+            .collect();
+        let exported_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        Ok(Self {
+            title: book.title.to_string(),
+            author: book.author.clone(),
+            exported_at,
+            line_width: book.line_width,
+            bookmarks,
+            highlights,
+        })
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        writeln!(markdown, "# {}", escape_markdown(&self.title)).unwrap();
+        if let Some(author) = &self.author {
+            writeln!(markdown, "\n_{}_", escape_markdown(author)).unwrap();
+        }
+        writeln!(
+            markdown,
+            "\n_Line numbers are counted at a line width of {}._",
+            self.line_width
+        )
+        .unwrap();
+        if !self.bookmarks.is_empty() {
+            writeln!(markdown, "\n## Bookmarks\n").unwrap();
+            for bookmark in &self.bookmarks {
+                write!(markdown, "- line {}", bookmark.line).unwrap();
+                if let Some(chapter) = &bookmark.chapter {
+                    write!(markdown, ", {}", escape_markdown(chapter)).unwrap();
+                }
+                writeln!(
+                    markdown,
+                    ": {}",
+                    emphasize(&bookmark.before, &bookmark.text, &bookmark.after)
+                )
+                .unwrap();
+            }
+        }
+        if !self.highlights.is_empty() {
+            writeln!(markdown, "\n## Highlights").unwrap();
+            let mut chapter = None;
+            for highlight in &self.highlights {
+                if highlight.chapter.is_some() && highlight.chapter != chapter {
+                    chapter = highlight.chapter.clone();
+                    let title = escape_markdown(chapter.as_deref().unwrap_or_default());
+                    writeln!(markdown, "\n### {title}").unwrap();
+                }
+                writeln!(
+                    markdown,
+                    "\n> {}",
+                    emphasize(&highlight.before, &highlight.text, &highlight.after)
+                )
+                .unwrap();
+                writeln!(markdown, "\n_line {}, {}_", highlight.line, highlight.color).unwrap();
+                if let Some(note) = &highlight.note {
+                    writeln!(markdown, "\n{}", escape_markdown(note)).unwrap();
+                }
+            }
+        }
+        markdown
+    }
+}
+
+/// Writes text in bold between its context, keeping surrounding spaces outside of the emphasis.
+fn emphasize(before: &str, text: &str, after: &str) -> String {
+    let lead = if text.starts_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    let trail = if text.ends_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    format!(
+        "{}{lead}**{}**{trail}{}",
+        escape_markdown(before),
+        escape_markdown(text.trim()),
+        escape_markdown(after)
+    )
+}
+
+/// Escapes the characters of book text that Markdown would read as emphasis, code, headings,
+/// lists or quotes.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            escaped.push('\n');
+        }
+        let indent = line.len() - line.trim_start().len();
+        for (index, char) in line.char_indices() {
+            let line_start = index == indent && matches!(char, '#' | '-' | '>');
+            if line_start || matches!(char, '\\' | '*' | '_' | '`') {
+                escaped.push('\\');
+            }
+            escaped.push(char);
+        }
+    }
+    escaped
+}
+
+/// Drops the partial word at the start of a context snippet.
+fn trim_start_to_word(text: &str, truncated: bool) -> String {
+    match text.find(' ') {
+        Some(index) if truncated => format!("…{}", &text[index..]),
+        _ => text.to_string(),
+    }
+}
+
+/// Drops the partial word at the end of a context snippet.
+fn trim_end_to_word(text: &str, truncated: bool) -> String {
+    match text.rfind(' ') {
+        Some(index) if truncated => format!("{}…", &text[..index]),
+        _ => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markdown_in_book_text() {
+        assert_eq!(escape_markdown("a *b* _c_ `d`"), r"a \*b\* \_c\_ \`d\`");
+        assert_eq!(escape_markdown("# one - two"), r"\# one - two");
+        assert_eq!(
+            escape_markdown("- item\n  > quote"),
+            "\\- item\n  \\> quote"
+        );
+        assert_eq!(escape_markdown(r"back\slash"), r"back\\slash");
+    }
+
+    #[test]
+    fn escapes_highlights_and_notes() {
+        let export = Export {
+            title: "*Emma*".to_string(),
+            author: Some("Jane_Austen".to_string()),
+            exported_at: 0,
+            line_width: 80,
+            bookmarks: vec![ExportedBookmark {
+                line: 3,
+                chapter: Some("# One_".to_string()),
+                text: "marked line ".to_string(),
+                before: "…said `she`".to_string(),
+                after: "and then…".to_string(),
+            }],
+            highlights: vec![ExportedHighlight {
+                line: 1,
+                chapter: Some("Part_1 #2".to_string()),
+                color: MarkerColor::Yellow,
+                text: "*stars*".to_string(),
+                note: Some("# not a heading".to_string()),
+                before: "> ".to_string(),
+                after: " _end_".to_string(),
+            }],
+        };
+        let markdown = export.to_markdown();
+        assert!(markdown.starts_with("# \\*Emma\\*\n"));
+        assert!(markdown.contains("\n_Jane\\_Austen_\n"));
+        assert!(markdown.contains("at a line width of 80"));
+        assert!(markdown.contains(r"- line 3, \# One\_: …said \`she\`**marked line** and then…"));
+        assert!(markdown.contains(r"### Part\_1 #2"));
+        assert!(markdown.contains(r"> \> **\*stars\*** \_end\_"));
+        assert!(markdown.contains("\n\\# not a heading\n"));
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Everything the reader can do from the keyboard, independent of the keys bound to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Cancel,
+    MoveDown,
+    MoveUp,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    GotoTop,
+    GotoBottom,
+    NextBookmark,
+    PrevBookmark,
+    NextChapter,
+    PrevChapter,
+    ToggleBookmark,
+    Define,
+    ToggleFocusMode,
+    CycleTheme,
+    SearchForward,
+    SearchBackward,
+    NextMatch,
+    PrevMatch,
+    OpenContents,
+    OpenMarkers,
+    OpenVocabulary,
+    IncreaseLineWidth,
+    DecreaseLineWidth,
+    ToggleMarker,
+    CycleMarkerColor,
+    EditNote,
+    CursorLeft,
+    CursorRight,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    ToggleVisual,
+    Yank,
+    RemoveEntry,
+    EditEntry,
+    Select,
+}
+
+/// Where keys are pressed: while reading or in an overlay like the table of contents. Keys
+/// may be bound to different actions in each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Reader,
+    Overlay,
+}
+
+impl Action {
+    /// Returns the name of the action as written in the config.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Cancel => "cancel",
+            Action::MoveDown => "move_down",
+            Action::MoveUp => "move_up",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::HalfPageDown => "half_page_down",
+            Action::HalfPageUp => "half_page_up",
+            Action::GotoTop => "goto_top",
+            Action::GotoBottom => "goto_bottom",
+            Action::NextBookmark => "next_bookmark",
+            Action::PrevBookmark => "prev_bookmark",
+            Action::NextChapter => "next_chapter",
+            Action::PrevChapter => "prev_chapter",
+            Action::ToggleBookmark => "toggle_bookmark",
+            Action::Define => "define",
+            Action::ToggleFocusMode => "toggle_focus_mode",
+            Action::CycleTheme => "cycle_theme",
+            Action::SearchForward => "search_forward",
+            Action::SearchBackward => "search_backward",
+            Action::NextMatch => "next_match",
+            Action::PrevMatch => "prev_match",
+            Action::OpenContents => "open_contents",
+            Action::OpenMarkers => "open_markers",
+            Action::OpenVocabulary => "open_vocabulary",
+            Action::IncreaseLineWidth => "increase_line_width",
+            Action::DecreaseLineWidth => "decrease_line_width",
+            Action::ToggleMarker => "toggle_marker",
+            Action::CycleMarkerColor => "cycle_marker_color",
+            Action::EditNote => "edit_note",
+            Action::CursorLeft => "cursor_left",
+            Action::CursorRight => "cursor_right",
+            Action::WordForward => "word_forward",
+            Action::WordBackward => "word_backward",
+            Action::WordEnd => "word_end",
+            Action::ToggleVisual => "toggle_visual",
+            Action::Yank => "yank",
+            Action::RemoveEntry => "remove_entry",
+            Action::EditEntry => "edit_entry",
+            Action::Select => "select",
+        }
+    }
+
+    /// Returns whether the action can be used in the given mode.
+    pub fn available_in(self, mode: Mode) -> bool {
+        match mode {
+            Mode::Reader => !matches!(
+                self,
+                Action::RemoveEntry | Action::EditEntry | Action::Select
+            ),
+            Mode::Overlay => matches!(
+                self,
+                Action::Quit
+                    | Action::Cancel
+                    | Action::MoveDown
+                    | Action::MoveUp
+                    | Action::OpenContents
+                    | Action::OpenMarkers
+                    | Action::OpenVocabulary
+                    | Action::EditNote
+                    | Action::Yank
+                    | Action::RemoveEntry
+                    | Action::EditEntry
+                    | Action::Select
+            ),
+        }
+    }
+
+    /// Returns whether the action shares a mode with another one, so that their keys could
+    /// be confused.
+    fn overlaps(self, other: Action) -> bool {
+        [Mode::Reader, Mode::Overlay]
+            .iter()
+            .any(|mode| self.available_in(*mode) && other.available_in(*mode))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+const DEFAULT_BINDINGS: [(Action, &[&str]); 40] = [
+    (Action::Quit, &["q"]),
+    (Action::Cancel, &["<Esc>"]),
+    (Action::MoveDown, &["j", "<Down>"]),
+    (Action::MoveUp, &["k", "<Up>"]),
+    (Action::PageDown, &["<PageDown>", "<C-f>", "<Space>"]),
+    (Action::PageUp, &["<PageUp>", "<C-b>"]),
+    (Action::HalfPageDown, &["<C-d>"]),
+    (Action::HalfPageUp, &["<C-u>"]),
+    (Action::GotoTop, &["gg", "<Home>"]),
+    (Action::GotoBottom, &["ge", "<End>"]),
+    (Action::NextBookmark, &["gn"]),
+    (Action::PrevBookmark, &["gp"]),
+    (Action::NextChapter, &["gj"]),
+    (Action::PrevChapter, &["gk"]),
+    (Action::ToggleBookmark, &["x"]),
+    (Action::Define, &["d"]),
+    (Action::ToggleFocusMode, &["f"]),
+    (Action::CycleTheme, &["T"]),
+    (Action::SearchForward, &["/"]),
+    (Action::SearchBackward, &["?"]),
+    (Action::NextMatch, &["n"]),
+    (Action::PrevMatch, &["N"]),
+    (Action::OpenContents, &["t"]),
+    (Action::OpenMarkers, &["H"]),
+    (Action::OpenVocabulary, &["W"]),
+    (Action::IncreaseLineWidth, &[">"]),
+    (Action::DecreaseLineWidth, &["<"]),
+    (Action::ToggleMarker, &["m"]),
+    (Action::CycleMarkerColor, &["c"]),
+    (Action::EditNote, &["a"]),
+    (Action::CursorLeft, &["h", "<Left>"]),
+    (Action::CursorRight, &["l", "<Right>"]),
+    (Action::WordForward, &["w"]),
+    (Action::WordBackward, &["b"]),
+    (Action::WordEnd, &["e"]),
+    (Action::ToggleVisual, &["v"]),
+    (Action::Yank, &["y"]),
+    (Action::RemoveEntry, &["x"]),
+    (Action::EditEntry, &["e"]),
+    (Action::Select, &["<Enter>"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    F(u8),
+}
+
+/// A key together with the modifiers held while pressing it. Shift is part of the char for
+/// printable keys, so `G` is written as `G` rather than `<S-g>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyPress {
+    pub fn new(key: Key) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    /// Parses a sequence of keys like `gg`, `<C-d>` or `<S-Down>j`. A `<` which does not start
+    /// a key name stands for itself.
+    pub fn parse_sequence(sequence: &str) -> anyhow::Result<Vec<KeyPress>> {
+        let chars = sequence.chars().collect::<Vec<_>>();
+        let mut keys = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let name = match chars[i] {
+                '<' => chars[i + 1..]
+                    .iter()
+                    .position(|char| *char == '>')
+                    .map(|end| chars[i + 1..i + 1 + end].iter().collect::<String>())
+                    .filter(|name| !name.is_empty()),
+                _ => None,
+            };
+            match name {
+                Some(name) => {
+                    keys.push(KeyPress::parse_name(&name)?);
+                    i += name.chars().count() + 2;
+                }
+                None => {
+                    keys.push(KeyPress::new(Key::Char(chars[i])));
+                    i += 1;
+                }
+            }
+        }
+        if keys.is_empty() {
+            anyhow::bail!("Empty key sequence");
+        }
+        Ok(keys)
+    }
+
+    /// Parses the name of a key between angle brackets, with modifiers like `C-`, `A-` and `S-`.
+    fn parse_name(name: &str) -> anyhow::Result<KeyPress> {
+        let mut press = KeyPress::new(Key::Esc);
+        let mut rest = name;
+        while let Some((modifier, tail)) = rest.split_once('-').filter(|(_, tail)| !tail.is_empty())
+        {
+            match modifier.to_lowercase().as_str() {
+                "c" | "ctrl" => press.ctrl = true,
+                "a" | "m" | "alt" => press.alt = true,
+                "s" | "shift" => press.shift = true,
+                _ => anyhow::bail!("Unknown modifier '{modifier}' in <{name}>"),
+            }
+            rest = tail;
+        }
+        press.key = match rest.to_lowercase().as_str() {
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "pageup" => Key::PageUp,
+            "pagedown" => Key::PageDown,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "enter" | "cr" | "return" => Key::Enter,
+            "esc" => Key::Esc,
+            "tab" => Key::Tab,
+            "bs" | "backspace" => Key::Backspace,
+            "del" | "delete" => Key::Delete,
+            "insert" => Key::Insert,
+            "space" => Key::Char(' '),
+            "lt" => Key::Char('<'),
+            "gt" => Key::Char('>'),
+            lower => match lower.strip_prefix('f').map(str::parse::<u8>) {
+                Some(Ok(number @ 1..=24)) => Key::F(number),
+                _ if rest.chars().count() == 1 => Key::Char(rest.chars().next().unwrap()),
+                _ => anyhow::bail!("Unknown key <{name}>"),
+            },
+        };
+        Ok(press.normalize())
+    }
+
+    /// Folds shift into printable chars, as terminals report them.
+    pub fn normalize(mut self) -> Self {
+        if let Key::Char(char) = self.key {
+            if self.shift {
+                self.key = Key::Char(char.to_uppercase().next().unwrap_or(char));
+            }
+            self.shift = false;
+        }
+        self
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.key {
+            Key::Char(' ') => "Space".to_string(),
+            Key::Char('<') => "lt".to_string(),
+            Key::Char(char) => char.to_string(),
+            Key::F(number) => format!("F{number}"),
+            key => format!("{key:?}"),
+        };
+        let modifiers = [(self.ctrl, "C-"), (self.alt, "A-"), (self.shift, "S-")]
+            .iter()
+            .filter(|(held, _)| *held)
+            .map(|(_, prefix)| *prefix)
+            .collect::<String>();
+        match (self.key, modifiers.is_empty()) {
+            (Key::Char(char), true) if char != ' ' && char != '<' => write!(f, "{name}"),
+            _ => write!(f, "<{modifiers}{name}>"),
+        }
+    }
+}
+
+/// What the keys pressed so far amount to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolved {
+    Action(Action),
+    /// The keys start a longer sequence.
+    Prefix,
+    None,
+}
+
+/// Maps key sequences to actions.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub bindings: Vec<(Vec<KeyPress>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::new(&BTreeMap::new()).unwrap()
+    }
+}
+
+impl Keymap {
+    /// Builds the keymap from the defaults, replacing the keys of every action listed in
+    /// `overrides`. An empty list unbinds an action. Fails if a key sequence is bound twice in
+    /// the same mode or is the beginning of another one, which could never be reached.
+    pub fn new(overrides: &BTreeMap<Action, Vec<String>>) -> anyhow::Result<Self> {
+        let mut bindings = Vec::new();
+        for (action, defaults) in DEFAULT_BINDINGS {
+            let sequences = match overrides.get(&action) {
+                Some(sequences) => sequences.iter().map(String::as_str).collect(),
+                None => defaults.to_vec(),
+            };
+            for sequence in sequences {
+                let keys = KeyPress::parse_sequence(sequence)
+                    .map_err(|err| anyhow::anyhow!("Invalid keys for {action}: {err}"))?;
+                bindings.push((keys, action));
+            }
+        }
+        for (i, (keys, action)) in bindings.iter().enumerate() {
+            for (other_keys, other_action) in &bindings[i + 1..] {
+                let (shorter, longer) = match keys.len() <= other_keys.len() {
+                    true => ((keys, action), (other_keys, other_action)),
+                    false => ((other_keys, other_action), (keys, action)),
+                };
+                if longer.0.starts_with(shorter.0) && action.overlaps(*other_action) {
+                    anyhow::bail!(
+                        "Keys '{}' of {} conflict with '{}' of {}",
+                        display(shorter.0),
+                        shorter.1,
+                        display(longer.0),
+                        longer.1
+                    );
+                }
+            }
+        }
+        Ok(Self { bindings })
+    }
+
+    /// Looks up the keys pressed so far among the actions of a mode.
+    pub fn resolve(&self, keys: &[KeyPress], mode: Mode) -> Resolved {
+        let mut resolved = Resolved::None;
+        let bindings = self
+            .bindings
+            .iter()
+            .filter(|(_, action)| action.available_in(mode));
+        for (sequence, action) in bindings {
+            if sequence == keys {
+                return Resolved::Action(*action);
+            }
+            if sequence.starts_with(keys) {
+                resolved = Resolved::Prefix;
+            }
+        }
+        resolved
+    }
+
+    /// Returns the first keys bound to an action, for hints.
+    pub fn keys_for(&self, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| *bound == action)
+            .map(|(keys, _)| display(keys))
+    }
+}
+
+fn display(keys: &[KeyPress]) -> String {
+    keys.iter().map(|key| key.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl(char: char) -> KeyPress {
+        KeyPress {
+            ctrl: true,
+            ..KeyPress::new(Key::Char(char))
+        }
+    }
+
+    fn keys(overrides: &[(Action, &[&str])]) -> BTreeMap<Action, Vec<String>> {
+        overrides
+            .iter()
+            .map(|(action, keys)| (*action, keys.iter().map(|key| key.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_key_names() {
+        let parse = |sequence| KeyPress::parse_sequence(sequence).unwrap();
+        assert_eq!(parse("<C-f>"), [ctrl('f')]);
+        assert_eq!(parse("<ctrl-F>"), [ctrl('F')]);
+        assert_eq!(parse("<Space>"), [KeyPress::new(Key::Char(' '))]);
+        assert_eq!(parse("<F12>"), [KeyPress::new(Key::F(12))]);
+        assert_eq!(
+            parse("<A-S-Down>"),
+            [KeyPress {
+                alt: true,
+                shift: true,
+                ..KeyPress::new(Key::Down)
+            }]
+        );
+        assert!(KeyPress::parse_sequence("<Hyper-x>").is_err());
+        assert!(KeyPress::parse_sequence("<Nope>").is_err());
+        assert!(KeyPress::parse_sequence("").is_err());
+    }
+
+    #[test]
+    fn parses_angle_brackets() {
+        let parse = |sequence| KeyPress::parse_sequence(sequence).unwrap();
+        let char = |char| KeyPress::new(Key::Char(char));
+        assert_eq!(parse("<lt>"), [char('<')]);
+        assert_eq!(parse("<gt>"), [char('>')]);
+        assert_eq!(parse("<"), [char('<')]);
+        assert_eq!(parse("<>"), [char('<'), char('>')]);
+        assert_eq!(parse("a<b"), [char('a'), char('<'), char('b')]);
+    }
+
+    #[test]
+    fn parses_multi_key_sequences() {
+        let parse = |sequence| KeyPress::parse_sequence(sequence).unwrap();
+        let char = |char| KeyPress::new(Key::Char(char));
+        assert_eq!(parse("gg"), [char('g'), char('g')]);
+        assert_eq!(parse("<C-w>j"), [ctrl('w'), char('j')]);
+        assert_eq!(
+            parse("g<End><lt>"),
+            [char('g'), KeyPress::new(Key::End), char('<')]
+        );
+        assert_eq!(display(&parse("<C-w><lt><Space>G")), "<C-w><lt><Space>G");
+    }
+
+    #[test]
+    fn folds_shift_into_chars() {
+        let shifted = KeyPress {
+            shift: true,
+            ..KeyPress::new(Key::Char('g'))
+        };
+        assert_eq!(shifted.normalize(), KeyPress::new(Key::Char('G')));
+        assert_eq!(
+            KeyPress::parse_sequence("<S-g>").unwrap(),
+            KeyPress::parse_sequence("G").unwrap()
+        );
+        // keys without a char keep shift
+        let down = KeyPress {
+            shift: true,
+            ..KeyPress::new(Key::Down)
+        };
+        assert_eq!(down.normalize(), down);
+    }
+
+    #[test]
+    fn default_bindings_build() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.resolve(&KeyPress::parse_sequence("gg").unwrap(), Mode::Reader),
+            Resolved::Action(Action::GotoTop)
+        );
+        assert_eq!(
+            keymap.resolve(&KeyPress::parse_sequence("g").unwrap(), Mode::Reader),
+            Resolved::Prefix
+        );
+        assert_eq!(
+            keymap.resolve(&KeyPress::parse_sequence("x").unwrap(), Mode::Overlay),
+            Resolved::Action(Action::RemoveEntry)
+        );
+        assert_eq!(
+            keymap.resolve(&KeyPress::parse_sequence("z").unwrap(), Mode::Reader),
+            Resolved::None
+        );
+        assert_eq!(
+            keymap.keys_for(Action::PageDown).as_deref(),
+            Some("<PageDown>")
+        );
+    }
+
+    #[test]
+    fn overrides_replace_default_keys() {
+        let keymap = Keymap::new(&keys(&[
+            (Action::MoveDown, &["<C-n>"]),
+            (Action::Quit, &[]),
+        ]))
+        .unwrap();
+        let resolve =
+            |sequence| keymap.resolve(&KeyPress::parse_sequence(sequence).unwrap(), Mode::Reader);
+        assert_eq!(resolve("<C-n>"), Resolved::Action(Action::MoveDown));
+        assert_eq!(resolve("j"), Resolved::None);
+        assert_eq!(resolve("q"), Resolved::None);
+    }
+
+    #[test]
+    fn rejects_conflicts_and_unreachable_sequences() {
+        // the same keys twice in one mode
+        assert!(Keymap::new(&keys(&[(Action::MoveDown, &["x"])])).is_err());
+        // a sequence that starts another one
+        assert!(Keymap::new(&keys(&[(Action::Define, &["g"])])).is_err());
+        assert!(Keymap::new(&keys(&[(Action::Define, &["ggx"])])).is_err());
+        // the same keys in different modes
+        assert!(Keymap::new(&keys(&[(Action::Yank, &["<Enter>"])])).is_err());
+        assert!(Keymap::new(&keys(&[(Action::CycleTheme, &["<Enter>"])])).is_ok());
+        assert!(Keymap::new(&keys(&[(Action::Define, &["<Nope>"])])).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::dictionary::DictionaryProvider;
+use crate::Definition;
+
+const VOWELS: &str = "aeiou";
+
+/// Inflected forms that suffix rules cannot undo, mapped to their lemmas.
+const IRREGULAR_FORMS: &[(&str, &str)] = &[
+    ("am", "be"),
+    ("are", "be"),
+    ("ate", "eat"),
+    ("became", "become"),
+    ("began", "begin"),
+    ("begun", "begin"),
+    ("being", "be"),
+    ("best", "good"),
+    ("better", "good"),
+    ("bore", "bear"),
+    ("borne", "bear"),
+    ("bought", "buy"),
+    ("broke", "break"),
+    ("broken", "break"),
+    ("brought", "bring"),
+    ("built", "build"),
+    ("came", "come"),
+    ("caught", "catch"),
+    ("children", "child"),
+    ("chose", "choose"),
+    ("chosen", "choose"),
+    ("dealt", "deal"),
+    ("did", "do"),
+    ("does", "do"),
+    ("done", "do"),
+    ("drawn", "draw"),
+    ("drew", "draw"),
+    ("driven", "drive"),
+    ("drove", "drive"),
+    ("eaten", "eat"),
+    ("elder", "old"),
+    ("eldest", "old"),
+    ("fallen", "fall"),
+    ("farther", "far"),
+    ("farthest", "far"),
+    ("fed", "feed"),
+    ("feet", "foot"),
+    ("fell", "fall"),
+    ("felt", "feel"),
+    ("fled", "flee"),
+    ("flew", "fly"),
+    ("flown", "fly"),
+    ("forgot", "forget"),
+    ("forgotten", "forget"),
+    ("fought", "fight"),
+    ("found", "find"),
+    ("further", "far"),
+    ("furthest", "far"),
+    ("gave", "give"),
+    ("geese", "goose"),
+    ("given", "give"),
+    ("gone", "go"),
+    ("got", "get"),
+    ("gotten", "get"),
+    ("grew", "grow"),
+    ("grown", "grow"),
+    ("had", "have"),
+    ("has", "have"),
+    ("heard", "hear"),
+    ("held", "hold"),
+    ("hid", "hide"),
+    ("hidden", "hide"),
+    ("hung", "hang"),
+    ("is", "be"),
+    ("kept", "keep"),
+    ("knew", "know"),
+    ("known", "know"),
+    ("lain", "lie"),
+    ("lay", "lie"),
+    ("least", "little"),
+    ("led", "lead"),
+    ("left", "leave"),
+    ("less", "little"),
+    ("lice", "louse"),
+    ("lost", "lose"),
+    ("made", "make"),
+    ("meant", "mean"),
+    ("men", "man"),
+    ("met", "meet"),
+    ("mice", "mouse"),
+    ("more", "much"),
+    ("most", "much"),
+    ("oxen", "ox"),
+    ("paid", "pay"),
+    ("people", "person"),
+    ("ran", "run"),
+    ("ridden", "ride"),
+    ("risen", "rise"),
+    ("rode", "ride"),
+    ("rose", "rise"),
+    ("said", "say"),
+    ("sang", "sing"),
+    ("sat", "sit"),
+    ("saw", "see"),
+    ("seen", "see"),
+    ("sent", "send"),
+    ("shaken", "shake"),
+    ("shook", "shake"),
+    ("slept", "sleep"),
+    ("sold", "sell"),
+    ("sought", "seek"),
+    ("spent", "spend"),
+    ("spoke", "speak"),
+    ("spoken", "speak"),
+    ("stood", "stand"),
+    ("struck", "strike"),
+    ("sung", "sing"),
+    ("swam", "swim"),
+    ("swum", "swim"),
+    ("taken", "take"),
+    ("taught", "teach"),
+    ("teeth", "tooth"),
+    ("thought", "think"),
+    ("threw", "throw"),
+    ("thrown", "throw"),
+    ("told", "tell"),
+    ("took", "take"),
+    ("understood", "understand"),
+    ("was", "be"),
+    ("went", "go"),
+    ("were", "be"),
+    ("woke", "wake"),
+    ("woken", "wake"),
+    ("women", "woman"),
+    ("wore", "wear"),
+    ("worn", "wear"),
+    ("worse", "bad"),
+    ("worst", "bad"),
+    ("written", "write"),
+    ("wrote", "write"),
+];
+
+/// Returns the forms to look up for a word: the word as selected, followed by its likely
+/// lemmas from the irregular forms and the English suffix rules.
+pub fn candidates(word: &str) -> Vec<String> {
+    let surface = word.trim_matches(|char: char| !char.is_alphanumeric());
+    let mut candidates = vec![surface.to_string()];
+    let mut push = |candidate: String| {
+        if candidate.chars().count() > 1 && !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    };
+    let word = surface.to_lowercase();
+    push(word.clone());
+    let word = word
+        .strip_suffix("'s")
+        .or_else(|| word.strip_suffix("’s"))
+        .unwrap_or(&word)
+        .to_string();
+    push(word.clone());
+    if let Ok(index) = IRREGULAR_FORMS.binary_search_by(|(form, _)| form.cmp(&word.as_str())) {
+        push(IRREGULAR_FORMS[index].1.to_string());
+    }
+    if let Some(stem) = word
+        .strip_suffix("ies")
+        .or_else(|| word.strip_suffix("ied"))
+    {
+        push(format!("{stem}y"));
+    }
+    if let Some(stem) = word.strip_suffix("ves") {
+        push(format!("{stem}f"));
+        push(format!("{stem}fe"));
+    }
+    if let Some(stem) = word.strip_suffix("ying") {
+        push(format!("{stem}ie"));
+    }
+    if let Some(stem) = word.strip_suffix("es") {
+        if ["s", "x", "z", "ch", "sh", "o"]
+            .iter()
+            .any(|ending| stem.ends_with(ending))
+        {
+            push(stem.to_string());
+        }
+    }
+    if let Some(stem) = word.strip_suffix('s') {
+        if !stem.ends_with('s') && !stem.ends_with('u') && !stem.ends_with('i') {
+            push(stem.to_string());
+        }
+    }
+    for suffix in ["ing", "ed", "est", "er"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            for candidate in verb_stems(stem) {
+                push(candidate);
+            }
+        }
+    }
+    for suffix in ["iest", "ier", "ily"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            push(format!("{stem}y"));
+        }
+    }
+    if let Some(stem) = word.strip_suffix("ly") {
+        push(stem.to_string());
+    }
+    candidates
+}
+
+/// Returns the likely bases of a stem left by stripping an inflection, most likely first:
+/// "runn" gives "run", "mak" gives "make" and "walk" stays "walk".
+fn verb_stems(stem: &str) -> Vec<String> {
+    let chars = stem.chars().collect::<Vec<_>>();
+    let is_vowel = |char: &char| VOWELS.contains(*char);
+    let mut stems = Vec::new();
+    match chars.as_slice() {
+        [.., a, b] if a == b && !is_vowel(a) && !"lsz".contains(*a) => {
+            stems.push(chars[..chars.len() - 1].iter().collect());
+            stems.push(stem.to_string());
+        }
+        [.., a, b, c] if !is_vowel(a) && is_vowel(b) && !is_vowel(c) && !"wxy".contains(*c) => {
+            stems.push(format!("{stem}e"));
+            stems.push(stem.to_string());
+        }
+        _ => {
+            stems.push(stem.to_string());
+            stems.push(format!("{stem}e"));
+        }
+    }
+    stems
+}
+
+/// Looks up the candidates of a word in turn, returning the first form that has a definition.
+pub async fn define(
+    provider: &dyn DictionaryProvider,
+    word: &str,
+) -> anyhow::Result<Option<(String, Definition)>> {
+    for candidate in candidates(word) {
+        if let Some(definition) = provider.define(&candidate).await? {
+            return Ok(Some((candidate, definition)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::Lookup;
+    use crate::dictionary::Meaning;
+
+    /// Knows only the words it was given.
+    #[derive(Debug)]
+    struct Words(&'static [&'static str]);
+
+    impl DictionaryProvider for Words {
+        fn name(&self) -> &str {
+            "words"
+        }
+
+        fn define<'a>(&'a self, word: &'a str) -> Lookup<'a> {
+            Box::pin(async move {
+                Ok(self.0.contains(&word).then(|| Definition {
+                    word: word.to_string(),
+                    phonetics: Vec::new(),
+                    meanings: vec![Meaning::default()],
+                }))
+            })
+        }
+    }
+
+    #[test]
+    fn irregular_forms_are_sorted() {
+        assert!(IRREGULAR_FORMS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn finds_lemmas_of_inflected_words() {
+        for (word, lemma) in [
+            ("running", "run"),
+            ("wolves", "wolf"),
+            ("said", "say"),
+            ("Children's", "child"),
+            ("making", "make"),
+            ("carried", "carry"),
+        ] {
+            assert!(candidates(word).contains(&lemma.to_string()), "{word}");
+        }
+        assert_eq!(candidates("run"), ["run"]);
+    }
+
+    #[test]
+    fn reports_the_form_that_matched() {
+        let provider = Words(&["run", "running", "wolf"]);
+        let define = |word| async_std::task::block_on(define(&provider, word)).unwrap();
+        assert_eq!(define("running").unwrap().0, "running");
+        assert_eq!(define("wolves").unwrap().0, "wolf");
+        assert!(define("said").is_none());
+    }
+}
@@ -1,83 +1,302 @@
+use std::env;
 use std::fmt;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
+use async_std::task;
+use async_std::task::JoinHandle;
 use serde::Deserialize;
 use serde::Serialize;
 
+use cache::CachedProvider;
+use dictionary::DictionaryProvider;
+use dictionary::DictionarySource;
+use keymap::Action;
+use keymap::Key;
+use keymap::KeyPress;
+use keymap::Mode;
+use keymap::Resolved;
+use review::Review;
+use settings::Settings;
+use text::Grapheme;
+use theme::Theme;
+use vocabulary::Vocabulary;
+use vocabulary::VocabularyEntry;
+
+pub mod cache;
+pub mod clipboard;
+pub mod color;
+pub mod dictionary;
+mod epub;
+pub mod export;
+pub mod keymap;
+pub mod lemma;
+pub mod review;
+pub mod settings;
+pub mod text;
+pub mod theme;
+pub mod vocabulary;
+
+pub use dictionary::Definition;
+
 const LICENSE_START: &str = "START OF THE PROJECT GUTENBERG";
 const LICENSE_END: &str = "END OF THE PROJECT GUTENBERG";
 
+pub const DEFAULT_LINE_WIDTH: usize = 80;
+pub const MIN_LINE_WIDTH: usize = 30;
+pub const GUTTER_WIDTH: usize = 10;
+pub const CONTEXT_LENGTH: usize = 32;
+pub const HEADING_LENGTH: usize = 60;
+/// How far from its stored offset the context of an anchor is looked for when the text changed.
+const RELOCATE_DISTANCE: usize = 50_000;
+pub const POSITION_INTERVAL: Duration = Duration::from_secs(30);
+pub const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+pub const LOOKUP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The number of lines a notch of the mouse wheel scrolls by.
+pub const WHEEL_LINES: usize = 3;
+pub const MAX_COUNT: usize = 99_999;
+
+const NUMBERED_HEADINGS: [&str; 9] = [
+    "CHAPTER", "BOOK", "PART", "VOLUME", "ACT", "SCENE", "STAVE", "CANTO", "LETTER",
+];
+const NAMED_HEADINGS: [&str; 7] = [
+    "PREFACE",
+    "INTRODUCTION",
+    "PROLOGUE",
+    "EPILOGUE",
+    "CONCLUSION",
+    "APPENDIX",
+    "AFTERWORD",
+];
+const NUMBER_WORDS: [&str; 20] = [
+    "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE", "TEN", "ELEVEN",
+    "TWELVE", "FIRST", "SECOND", "THIRD", "FOURTH", "FIFTH", "SIXTH", "SEVENTH", "LAST",
+];
+const ROMAN_NUMERALS: [(usize, &str); 13] = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+/// The highest roman numeral taken as a chapter number on a line of its own, so that words
+/// like `MIX` are not.
+const MAX_BARE_NUMERAL: usize = 300;
+
+/// The state of a book: bookmarks, highlights, the reading position and the preferences
+/// overriding the global settings for it. Stored in `$XDG_DATA_HOME/booklet/books`, or next to
+/// the book in `.booklet_<filename>` if `sidecar` is set in the settings.
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
-    pub bookmarks: Vec<usize>,
-    pub markers: Vec<(usize, usize, usize)>,
+    pub bookmarks: Vec<Anchor>,
+    pub markers: Vec<Marker>,
     pub focus_mode: Option<bool>,
+    pub line_width: Option<usize>,
+    pub position: Option<Anchor>,
+    pub last_read: Option<u64>,
+    pub dictionary: Option<DictionarySource>,
+    /// The theme chosen for this book instead of the one in the settings.
+    pub theme: Option<String>,
+    /// Where the state is written to.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
 }
 
-#[derive(Debug)]
-pub struct Definition {
-    pub word: String,
-    pub list: Vec<String>,
+/// A position in the normalized text of a book together with a fingerprint of the text
+/// following it, so that it can be found again when the text shifts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anchor {
+    pub offset: usize,
+    pub context: String,
 }
 
-impl Definition {
-    pub fn from_json(value: &serde_json::Value) -> Option<Definition> {
-        let entry = value.as_array()?.get(0)?;
-        let word = entry.get("word")?.as_str()?;
-        let mut list = Vec::new();
-        let meanings = entry.get("meanings")?.as_array()?;
-        for meaning in meanings {
-            let definitions = meaning.get("definitions")?.as_array()?;
-            for definition in definitions {
-                let sentence = definition.get("definition")?.as_str()?;
-                list.push(sentence.to_string());
-            }
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Marker {
+    pub anchor: Anchor,
+    pub length: usize,
+    #[serde(default)]
+    pub color: MarkerColor,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkerColor {
+    #[default]
+    Yellow,
+    Green,
+    Blue,
+    Pink,
+}
+
+impl MarkerColor {
+    pub fn next(self) -> Self {
+        match self {
+            MarkerColor::Yellow => MarkerColor::Green,
+            MarkerColor::Green => MarkerColor::Blue,
+            MarkerColor::Blue => MarkerColor::Pink,
+            MarkerColor::Pink => MarkerColor::Yellow,
+        }
+    }
+
+    pub fn code(self) -> char {
+        match self {
+            MarkerColor::Yellow => Codes::BACKGROUND_MARKER,
+            MarkerColor::Green => Codes::BACKGROUND_MARKER_GREEN,
+            MarkerColor::Blue => Codes::BACKGROUND_MARKER_BLUE,
+            MarkerColor::Pink => Codes::BACKGROUND_MARKER_PINK,
         }
-        Some(Definition {
-            word: word.to_string(),
-            list,
-        })
     }
 }
 
-impl fmt::Display for Definition {
+impl fmt::Display for MarkerColor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.word)?;
-        writeln!(f)?;
-        for (i, item) in self.list.iter().enumerate() {
-            write!(f, "{i}. {item}")?;
-        }
-        Ok(())
+        let name = match self {
+            MarkerColor::Yellow => "yellow",
+            MarkerColor::Green => "green",
+            MarkerColor::Blue => "blue",
+            MarkerColor::Pink => "pink",
+        };
+        write!(f, "{name}")
     }
 }
 
 impl Config {
-    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+    /// Loads the state of the book at `path`. Without `sidecar`, a sidecar file left by older
+    /// versions is still read if there is no state in the data directory yet.
+    pub fn from_path(path: &str, book: &Book, sidecar: bool) -> anyhow::Result<Self> {
+        let file = match sidecar {
+            true => Config::sidecar_path(path),
+            // without a home directory there is no data directory either
+            false => Config::data_path(path).or_else(|| Config::sidecar_path(path)),
+        };
+        let existing = file
+            .clone()
+            .filter(|file| file.exists())
+            .or_else(|| Config::sidecar_path(path).filter(|file| file.exists()));
+        let mut config = match existing {
+            Some(existing) => {
+                let content = fs::read_to_string(existing)?;
+                let mut table = toml::from_str::<toml::Table>(&content)?;
+                Config::migrate(&mut table, book)?;
+                let mut config = Self::deserialize(table)?;
+                config.relocate(book);
+                config
+            }
+            None => Config::default(),
+        };
+        config.file = file;
+        Ok(config)
+    }
+
+    /// Returns the path of the hidden file next to the book.
+    pub fn sidecar_path(path: &str) -> Option<PathBuf> {
         let mut path_buf = PathBuf::from(path);
-        if let Some(filename) = path_buf.file_name() {
-            let filename = filename.to_os_string().into_string().unwrap();
-            path_buf.pop();
-            path_buf.push(format!(".booklet_{filename}"));
-            if !path_buf.exists() {
-                return Ok(Config::default());
+        let filename = path_buf.file_name()?.to_string_lossy().to_string();
+        path_buf.pop();
+        path_buf.push(format!(".booklet_{filename}"));
+        Some(path_buf)
+    }
+
+    /// Returns the path of the state file in `$XDG_DATA_HOME/booklet/books`, named after the
+    /// book and a hash of its absolute path so that books with the same name do not collide.
+    pub fn data_path(path: &str) -> Option<PathBuf> {
+        let filename = PathBuf::from(path)
+            .file_name()?
+            .to_string_lossy()
+            .to_string();
+        let absolute = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let hash = absolute
+            .to_string_lossy()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        let mut data_path = xdg_dir("XDG_DATA_HOME", ".local/share")?;
+        data_path.push("booklet");
+        data_path.push("books");
+        data_path.push(format!("{filename}-{hash:016x}.toml"));
+        Some(data_path)
+    }
+
+    /// Converts bookmarks stored as line numbers and markers stored as `(line, start, end)`
+    /// into anchors. Those line numbers refer to the lines of the source text.
+    fn migrate(table: &mut toml::Table, book: &Book) -> anyhow::Result<()> {
+        if let Some(toml::Value::Array(bookmarks)) = table.get_mut("bookmarks") {
+            for bookmark in bookmarks.iter_mut() {
+                if let Some(line) = bookmark.as_integer() {
+                    let offset = book.source_offset(line as usize, 0);
+                    *bookmark = toml::Value::try_from(book.anchor(offset))?;
+                }
+            }
+        }
+        if let Some(toml::Value::Array(markers)) = table.get_mut("markers") {
+            for marker in markers.iter_mut() {
+                let values = match marker.as_array() {
+                    Some(values) => values
+                        .iter()
+                        .filter_map(|value| value.as_integer())
+                        .map(|value| value as usize)
+                        .collect::<Vec<_>>(),
+                    None => continue,
+                };
+                if let [line, start, end] = values[..] {
+                    let offset = book.source_offset(line, start);
+                    let length = book.source_offset(line, end).saturating_sub(offset);
+                    let anchor = book.anchor(offset);
+                    *marker = toml::Value::try_from(Marker {
+                        anchor,
+                        length,
+                        ..Default::default()
+                    })?;
+                }
             }
-            let content = fs::read_to_string(path_buf)?;
-            let mut config = toml::from_str::<Self>(&content)?;
-            config.bookmarks.sort();
-            return Ok(config);
         }
-        Ok(Config::default())
+        Ok(())
     }
 
-    pub fn write(&self, path: &str) -> anyhow::Result<()> {
-        let mut path_buf = PathBuf::from(path);
-        if let Some(filename) = path_buf.file_name() {
-            let filename = filename.to_os_string().into_string().unwrap();
-            path_buf.pop();
-            path_buf.push(format!(".booklet_{filename}"));
+    /// Moves every anchor to where its context is found in the current text.
+    pub fn relocate(&mut self, book: &Book) {
+        for bookmark in self.bookmarks.iter_mut() {
+            if let Some(offset) = book.relocate(bookmark) {
+                *bookmark = book.anchor(offset);
+            }
+        }
+        for marker in self.markers.iter_mut() {
+            if let Some(offset) = book.relocate(&marker.anchor) {
+                marker.anchor = book.anchor(offset);
+            }
+        }
+        if let Some(position) = &mut self.position {
+            if let Some(offset) = book.relocate(position) {
+                *position = book.anchor(offset);
+            }
+        }
+        self.bookmarks.sort_by_key(|bookmark| bookmark.offset);
+        self.bookmarks.dedup_by_key(|bookmark| bookmark.offset);
+        self.markers.sort_by_key(|marker| marker.anchor.offset);
+    }
+
+    pub fn write(&self) -> anyhow::Result<()> {
+        if let Some(file) = &self.file {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+            }
             let content = toml::to_string(self)?;
-            fs::write(path_buf, content)?;
+            fs::write(file, content)?;
         }
         Ok(())
     }
@@ -85,26 +304,474 @@ impl Config {
 
 #[derive(Debug)]
 pub struct Book {
+    pub title: String,
+    pub author: Option<String>,
     pub lines: Vec<String>,
     pub line_count: usize,
     pub line_width: usize,
+    pub source: Vec<String>,
+    pub text: Vec<char>,
+    pub line_offsets: Vec<usize>,
+    pub toc: Vec<(String, usize)>,
+    /// Problems found while reading the book that did not stop it from opening.
+    pub warnings: Vec<String>,
 }
 
 impl Book {
     pub fn from_path(path: &str) -> anyhow::Result<Self> {
-        let mut content = fs::read_to_string(path)?;
-        content = Book::remove_license(&content);
-        content = Book::highlight_italic(&content);
-        let lines = content
+        let mut warnings = Vec::new();
+        let (content, title, author) = if Book::is_epub(path) {
+            let epub = epub::read(path)?;
+            let content = Book::remove_license(&epub.content);
+            warnings = epub.warnings;
+            (content, epub.title, epub.author)
+        } else {
+            let content = fs::read_to_string(path)?;
+            let title = Book::read_header(&content, "Title:");
+            let author = Book::read_header(&content, "Author:");
+            let content = Book::remove_license(&content);
+            (Book::highlight_italic(&content), title, author)
+        };
+        let title = title.or_else(|| {
+            PathBuf::from(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        });
+        let mut book = Book::new(&content, title.unwrap_or_default(), author);
+        book.warnings = warnings;
+        Ok(book)
+    }
+
+    /// Builds a book from its text, laid out at the default line width.
+    pub fn new(content: &str, title: String, author: Option<String>) -> Self {
+        let source = content
             .lines()
             .map(|line| line.to_string())
             .collect::<Vec<String>>();
-        let line_count = lines.len();
-        Ok(Self {
-            lines,
-            line_count,
-            line_width: 80,
-        })
+        let mut book = Self {
+            title,
+            author,
+            lines: Vec::new(),
+            line_count: 0,
+            line_width: 0,
+            source,
+            text: Vec::new(),
+            line_offsets: Vec::new(),
+            toc: Vec::new(),
+            warnings: Vec::new(),
+        };
+        book.reflow(DEFAULT_LINE_WIDTH);
+        book
+    }
+
+    /// Joins the hard-wrapped paragraphs of the source and wraps them again so that each line
+    /// fits into `line_width` columns including the gutter. Blocks which look like poetry,
+    /// tables or indented text are kept as they are.
+    pub fn reflow(&mut self, line_width: usize) {
+        let text_width = line_width.saturating_sub(GUTTER_WIDTH).max(1);
+        let source_width = Book::source_width(&self.source);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while start < self.source.len() {
+            if is_blank(&self.source[start]) {
+                lines.push(String::new());
+                start += 1;
+                continue;
+            }
+            let end = (start..self.source.len())
+                .find(|i| is_blank(&self.source[*i]))
+                .unwrap_or(self.source.len());
+            let block = &self.source[start..end];
+            if Book::is_preformatted(block, source_width) {
+                lines.extend(block.iter().cloned());
+            } else {
+                let words = block
+                    .iter()
+                    .flat_map(|line| line.split_whitespace())
+                    .collect::<Vec<_>>();
+                lines.extend(Book::wrap(&words, text_width));
+            }
+            start = end;
+        }
+        self.lines = balance_codes(lines);
+        self.line_count = self.lines.len();
+        self.line_width = line_width;
+        self.index();
+        self.toc = self.detect_chapters();
+    }
+
+    /// Detects chapter headings: numbered headings like `CHAPTER IV` or `BOOK II`, roman
+    /// numerals on their own line and all-caps lines preceded by blank lines. A short line
+    /// directly following a heading is taken as its title.
+    fn detect_chapters(&self) -> Vec<(String, usize)> {
+        let mut toc = Vec::new();
+        let plain = self
+            .lines
+            .iter()
+            .map(|line| normalize(line).join(" "))
+            .collect::<Vec<_>>();
+        for (i, line) in plain.iter().enumerate() {
+            if line.is_empty() || line.chars().count() > HEADING_LENGTH {
+                continue;
+            }
+            let blank_before = (0..i).rev().take_while(|j| plain[*j].is_empty()).count();
+            if blank_before == 0 && i > 0 {
+                continue;
+            }
+            let next = plain
+                .get(i + 1)
+                .map(|line| line.as_str())
+                .unwrap_or_default();
+            let after_next = plain
+                .get(i + 2)
+                .map(|line| line.as_str())
+                .unwrap_or_default();
+            let has_subtitle = !next.is_empty()
+                && next.chars().count() <= HEADING_LENGTH
+                && after_next.is_empty()
+                && !is_numbered_heading(next);
+            if !next.is_empty() && !has_subtitle {
+                continue;
+            }
+            let is_heading = is_numbered_heading(line)
+                || roman_value(line.trim_end_matches('.'))
+                    .is_some_and(|value| value <= MAX_BARE_NUMERAL)
+                || (blank_before >= 2 && next.is_empty() && is_caps_heading(line));
+            if !is_heading {
+                continue;
+            }
+            let title = if has_subtitle {
+                format!("{line} {next}")
+            } else {
+                line.to_string()
+            };
+            toc.push((title, i));
+        }
+        toc
+    }
+
+    /// Returns the index of the chapter containing the given line.
+    pub fn chapter_at(&self, line_number: usize) -> Option<usize> {
+        self.toc
+            .partition_point(|(_, line)| line <= &line_number)
+            .checked_sub(1)
+    }
+
+    /// Builds the normalized text: all words without formatting codes, separated by single
+    /// spaces. It only depends on the words of the book, not on how they are split into lines,
+    /// which makes offsets into it stable across reflows. Words broken by `wrap` are joined
+    /// again.
+    fn index(&mut self) {
+        let mut text = Vec::new();
+        let mut line_offsets = Vec::new();
+        let mut joined = false;
+        for line in &self.lines {
+            let words = normalize(line);
+            let separator = (!text.is_empty() && !joined) as usize;
+            joined = has_soft_break(line);
+            if words.is_empty() {
+                line_offsets.push(text.len() + separator);
+                continue;
+            }
+            if separator > 0 {
+                text.push(' ');
+            }
+            line_offsets.push(text.len());
+            text.extend(words.join(" ").chars());
+        }
+        self.text = text;
+        self.line_offsets = line_offsets;
+    }
+
+    /// Returns the line containing the given offset into the normalized text.
+    pub fn line_at(&self, offset: usize) -> usize {
+        self.line_offsets
+            .partition_point(|item| item <= &offset)
+            .saturating_sub(1)
+    }
+
+    /// Returns the offset into the normalized text of the char at `index` in the given line.
+    pub fn offset_at(&self, line_number: usize, index: usize) -> usize {
+        match (
+            self.lines.get(line_number),
+            self.line_offsets.get(line_number),
+        ) {
+            (Some(line), Some(offset)) => offset + normalized_column(line, index),
+            _ => self.text.len(),
+        }
+    }
+
+    /// Returns the line and the char index within that line of an offset into the normalized
+    /// text.
+    pub fn position_at(&self, offset: usize) -> (usize, usize) {
+        let line_number = self.line_at(offset);
+        let line = match self.lines.get(line_number) {
+            Some(line) => line,
+            None => return (line_number, 0),
+        };
+        let column = offset.saturating_sub(self.line_offsets[line_number]);
+        (line_number, char_index(line, column))
+    }
+
+    /// Returns the graphemes of a line, the units selections and the cursor move by.
+    pub fn graphemes(&self, line_number: usize) -> Vec<Grapheme> {
+        self.lines
+            .get(line_number)
+            .map(|line| text::graphemes(line))
+            .unwrap_or_default()
+    }
+
+    /// Returns the char index of the grapheme displayed at a column of a line, or of the last
+    /// grapheme if the column is past the end of the line.
+    pub fn index_at_column(&self, line_number: usize, column: usize) -> Option<usize> {
+        let line = self.lines.get(line_number)?;
+        text::index_at(line, column)
+            .or_else(|| text::graphemes(line).last().map(|grapheme| grapheme.start))
+    }
+
+    /// Returns the column a char index of a line is displayed at.
+    pub fn column_at_index(&self, line_number: usize, index: usize) -> usize {
+        self.lines
+            .get(line_number)
+            .map(|line| text::column_at(line, index))
+            .unwrap_or_default()
+    }
+
+    /// Returns the char range of the grapheme at a char index of a line.
+    pub fn grapheme_at(&self, line_number: usize, index: usize) -> Option<(usize, usize)> {
+        text::grapheme_at(self.lines.get(line_number)?, index)
+    }
+
+    /// Widens a range of char indexes of a line to whole graphemes.
+    pub fn snap(&self, line_number: usize, range: (usize, usize)) -> (usize, usize) {
+        match self.lines.get(line_number) {
+            Some(line) => text::snap(line, range),
+            None => range,
+        }
+    }
+
+    /// Returns the offset into the normalized text of the char at `index` in the given line of
+    /// the source, as it was before reflowing.
+    pub fn source_offset(&self, line_number: usize, index: usize) -> usize {
+        let mut offset = 0;
+        for (i, line) in self.source.iter().enumerate() {
+            let words = normalize(line);
+            let separator = (offset > 0) as usize;
+            if i == line_number {
+                return offset + separator + normalized_column(line, index);
+            }
+            if !words.is_empty() {
+                offset += separator + words.join(" ").chars().count();
+            }
+        }
+        self.text.len()
+    }
+
+    /// Returns `length` chars of the normalized text starting at `offset`.
+    pub fn text_at(&self, offset: usize, length: usize) -> String {
+        let start = offset.min(self.text.len());
+        let end = (offset + length).min(self.text.len());
+        self.text[start..end].iter().collect()
+    }
+
+    /// Returns the sentence containing an offset into the normalized text, without crossing
+    /// paragraph boundaries.
+    pub fn sentence_at(&self, offset: usize) -> String {
+        let line_number = self.line_at(offset);
+        let mut first = line_number;
+        while first > 0 && !is_blank(&self.lines[first - 1]) {
+            first -= 1;
+        }
+        let mut last = line_number;
+        while last + 1 < self.lines.len() && !is_blank(&self.lines[last + 1]) {
+            last += 1;
+        }
+        let paragraph_start = self.line_offsets.get(first).copied().unwrap_or_default();
+        let paragraph_end = self
+            .line_offsets
+            .get(last + 1)
+            .map(|offset| offset.saturating_sub(1))
+            .unwrap_or(self.text.len())
+            .min(self.text.len());
+        let offset = offset.clamp(paragraph_start, paragraph_end);
+        // a sentence ends with a terminator, optionally followed by closing quotes
+        let is_end = |index: usize| {
+            if !matches!(self.text.get(index), Some('.' | '!' | '?')) {
+                return false;
+            }
+            let mut next = index + 1;
+            while matches!(self.text.get(next), Some('"' | '”' | '’' | '\'' | ')')) {
+                next += 1;
+            }
+            next >= paragraph_end || self.text[next] == ' '
+        };
+        let start = (paragraph_start..offset)
+            .rev()
+            .find(|&index| {
+                self.text[index] == ' ' && index > 0 && {
+                    let mut previous = index - 1;
+                    while previous > paragraph_start
+                        && matches!(self.text[previous], '"' | '”' | '’' | '\'' | ')')
+                    {
+                        previous -= 1;
+                    }
+                    is_end(previous)
+                }
+            })
+            .map(|index| index + 1)
+            .unwrap_or(paragraph_start);
+        let mut end = (offset..paragraph_end)
+            .find(|&index| is_end(index))
+            .map(|index| index + 1)
+            .unwrap_or(paragraph_end);
+        while end < paragraph_end && matches!(self.text[end], '"' | '”' | '’' | '\'' | ')') {
+            end += 1;
+        }
+        self.text[start..end].iter().collect()
+    }
+
+    pub fn anchor(&self, offset: usize) -> Anchor {
+        let offset = offset.min(self.text.len());
+        let end = (offset + CONTEXT_LENGTH).min(self.text.len());
+        Anchor {
+            offset,
+            context: self.text[offset..end].iter().collect(),
+        }
+    }
+
+    /// Finds the offset an anchor points to in the current text. When the context is no longer
+    /// found at the stored offset, the closest occurrence of the context within
+    /// `RELOCATE_DISTANCE` is used, falling back to shorter prefixes of it.
+    pub fn relocate(&self, anchor: &Anchor) -> Option<usize> {
+        let context = anchor.context.chars().collect::<Vec<_>>();
+        let offset = anchor.offset.min(self.text.len());
+        if context.is_empty() || self.text[offset..].starts_with(&context) {
+            return Some(offset);
+        }
+        let distance = RELOCATE_DISTANCE.min(self.text.len());
+        let mut length = context.len();
+        while length >= CONTEXT_LENGTH / 4 {
+            let needle = &context[..length];
+            // search outwards so that the closest occurrence is found first
+            let closest = (0..=distance)
+                .flat_map(|distance| [offset.checked_sub(distance), Some(offset + distance)])
+                .flatten()
+                .find(|start| self.text.get(*start..start + length) == Some(needle));
+            if closest.is_some() {
+                return closest;
+            }
+            length /= 2;
+        }
+        None
+    }
+
+    /// Estimates the width the source was hard-wrapped at, ignoring the few longest lines.
+    fn source_width(source: &[String]) -> usize {
+        let mut widths = source
+            .iter()
+            .map(|line| text_width(line))
+            .filter(|width| width > &0)
+            .collect::<Vec<_>>();
+        if widths.is_empty() {
+            return 0;
+        }
+        widths.sort();
+        widths[(widths.len() - 1) * 98 / 100]
+    }
+
+    /// Returns whether a block of source lines is laid out by hand and must not be reflowed:
+    /// indented verse or quotes, tables and lines broken before they were full.
+    fn is_preformatted(block: &[String], source_width: usize) -> bool {
+        let indented = |line: &String| line.starts_with(char::is_whitespace);
+        // a first-line indent alone starts an ordinary paragraph
+        if block.len() == 1 {
+            return indented(&block[0]) && text_width(&block[0]) <= source_width;
+        }
+        if block[1..].iter().all(indented) {
+            return true;
+        }
+        // columns and table borders have to repeat on most lines
+        let columns = block
+            .iter()
+            .filter(|line| line.contains("   ") || line.contains('\t') || line.contains('|'))
+            .count();
+        if columns * 2 > block.len() {
+            return true;
+        }
+        // a line is broken deliberately if the first word of the next line would have fit
+        let deliberate = block
+            .windows(2)
+            .filter(|lines| {
+                let next_word = lines[1].split_whitespace().next().unwrap_or_default();
+                text_width(&lines[0]) + 1 + text_width(next_word) < source_width
+            })
+            .count();
+        deliberate * 2 > block.len() - 1
+    }
+
+    /// Wraps words into lines of at most `max_width` columns. Words which do not fit on a line
+    /// of their own and text written without spaces, like Chinese or Japanese, are broken
+    /// between graphemes, and the line is ended with a soft break.
+    fn wrap(words: &[&str], max_width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        let mut width = 0;
+        for word in words {
+            let mut word = word.to_string();
+            loop {
+                let word_width = text_width(&word);
+                let space = !line.is_empty() as usize;
+                if width + space + word_width <= max_width {
+                    break;
+                }
+                let available = max_width.saturating_sub(width + space);
+                let split = match text::is_wide(&word) || line.is_empty() {
+                    true => text::split(&word, available, line.is_empty()),
+                    false => None,
+                };
+                match split {
+                    Some((head, tail)) => {
+                        if space > 0 {
+                            line.push(' ');
+                        }
+                        line.push_str(&head);
+                        line.push(Codes::SOFT_BREAK);
+                        lines.push(line);
+                        word = tail;
+                    }
+                    None if !line.is_empty() => lines.push(line),
+                    None => break,
+                }
+                line = String::new();
+                width = 0;
+            }
+            if !line.is_empty() {
+                line.push(' ');
+                width += 1;
+            }
+            width += text_width(&word);
+            line.push_str(&word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Reads a field like `Title: ...` from the header of a Project Gutenberg text.
+    fn read_header(content: &str, field: &str) -> Option<String> {
+        content
+            .lines()
+            .take_while(|line| !line.contains(LICENSE_START))
+            .find_map(|line| line.strip_prefix(field))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn is_epub(path: &str) -> bool {
+        PathBuf::from(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
     }
 
     fn remove_license(content: &str) -> String {
@@ -158,6 +825,124 @@ impl Book {
     }
 }
 
+#[derive(Debug, Default)]
+pub struct Search {
+    pub query: String,
+    pub backward: bool,
+    pub origin: usize,
+    pub matches: Vec<(usize, usize, usize)>,
+    /// The index of the match moved to last.
+    pub current: Option<usize>,
+}
+
+impl Search {
+    /// Finds all case-insensitive occurrences of the query in the given lines, ignoring
+    /// formatting codes. Matches are `(line, start, end)` char ranges of the stored lines.
+    pub fn find(lines: &[String], query: &str) -> Vec<(usize, usize, usize)> {
+        let query = query
+            .chars()
+            .flat_map(|char| char.to_lowercase())
+            .collect::<Vec<_>>();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut matches = Vec::new();
+        for (line_number, line) in lines.iter().enumerate() {
+            let chars = line
+                .chars()
+                .enumerate()
+                .filter(|(_, char)| !Codes::is_code(*char))
+                .flat_map(|(i, char)| char.to_lowercase().map(move |lower| (i, lower)))
+                .collect::<Vec<_>>();
+            let mut i = 0;
+            while i + query.len() <= chars.len() {
+                if chars[i..i + query.len()]
+                    .iter()
+                    .zip(&query)
+                    .all(|((_, char), other)| char == other)
+                {
+                    let (start, _) = chars[i];
+                    let (end, _) = chars[i + query.len() - 1];
+                    matches.push((line_number, start, end + 1));
+                    i += query.len();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        matches
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    Contents(usize),
+    Markers(usize),
+    Note(usize),
+    Vocabulary(usize),
+}
+
+/// A range of text between two positions given as `(line, char index)` into the lines of
+/// the book. The start is inclusive and the end exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl Selection {
+    /// Creates a selection between two positions given in any order.
+    pub fn new(a: (usize, usize), b: (usize, usize)) -> Self {
+        Self {
+            start: a.min(b),
+            end: a.max(b),
+        }
+    }
+
+    /// Creates a selection within a single line.
+    pub fn line(line_number: usize, start: usize, end: usize) -> Self {
+        Self::new((line_number, start), (line_number, end))
+    }
+
+    /// Returns the char range covered on the given line, which is `len` chars long.
+    pub fn range_on(&self, line_number: usize, len: usize) -> Option<(usize, usize)> {
+        if line_number < self.start.0 || line_number > self.end.0 {
+            return None;
+        }
+        let start = if line_number == self.start.0 {
+            self.start.1
+        } else {
+            0
+        };
+        let end = if line_number == self.end.0 {
+            self.end.1
+        } else {
+            len
+        };
+        Some((start.min(len), end.min(len)))
+    }
+}
+
+/// Movements of the keyboard cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    WordForward,
+    WordBackward,
+    WordEnd,
+}
+
+/// A dictionary lookup running in the background.
+#[derive(Debug)]
+pub struct PendingLookup {
+    pub selection: Selection,
+    pub word: String,
+    pub started: Instant,
+    pub task: JoinHandle<()>,
+    pub receiver: mpsc::Receiver<anyhow::Result<Option<(String, Definition)>>>,
+}
+
 #[derive(Debug)]
 pub struct State {
     pub path: String,
@@ -168,13 +953,41 @@ pub struct State {
     pub line_number: usize,
     pub pad_left: usize,
     pub update_screen: bool,
-    pub selection: Option<(usize, usize, usize)>,
-    pub definition: Option<((usize, usize, usize), Definition)>,
+    pub selection: Option<Selection>,
+    pub definition: Option<(Selection, Definition)>,
     pub message: Option<String>,
+    pub search: Option<Search>,
+    pub overlay: Option<Overlay>,
+    pub position_saved: Instant,
+    pub marker_color: MarkerColor,
+    pub dictionary: Arc<dyn DictionaryProvider>,
+    pub lookup: Option<PendingLookup>,
+    /// How long a lookup may take before it fails.
+    pub lookup_timeout: Duration,
+    pub vocabulary: Vocabulary,
+    /// The keyboard cursor as `(line, char index)`, kept on the focus line.
+    pub cursor: Option<(usize, usize)>,
+    /// Where the visual selection started, as `(line, char index)`.
+    pub visual: Option<(usize, usize)>,
+    /// Where the mouse button was pressed, as `(line, char index)`.
+    pub drag: Option<(usize, usize)>,
+    pub settings: Settings,
+    pub theme: Theme,
+    /// The keys of a sequence typed so far.
+    pub pending_keys: Vec<KeyPress>,
+    /// The number typed before an action, like the 25 of `25j`.
+    pub count: Option<usize>,
 }
 
 impl State {
-    pub fn new(path: &str, config: Config, book: Book) -> Self {
+    pub fn new(path: &str, settings: Settings, config: Config, book: Book) -> Self {
+        let source = config
+            .dictionary
+            .clone()
+            .or(settings.dictionary.clone())
+            .unwrap_or_default();
+        let dictionary: Arc<dyn DictionaryProvider> = Arc::new(CachedProvider::new(&source));
+        let theme = settings.theme_or_default(config.theme.as_deref());
         Self {
             path: path.to_string(),
             config,
@@ -187,6 +1000,106 @@ impl State {
             update_screen: false,
             definition: None,
             message: None,
+            search: None,
+            overlay: None,
+            position_saved: Instant::now(),
+            marker_color: MarkerColor::default(),
+            dictionary,
+            lookup: None,
+            lookup_timeout: LOOKUP_TIMEOUT,
+            vocabulary: Vocabulary::default(),
+            cursor: None,
+            visual: None,
+            drag: None,
+            settings,
+            theme,
+            pending_keys: Vec::new(),
+            count: None,
+        }
+    }
+
+    /// Adds a key to the sequence typed so far and returns its action together with the count
+    /// typed before it once the sequence is complete. Digits not bound to an action make up the
+    /// count. Esc abandons an unfinished sequence. Keys pressed while an overlay is open are
+    /// looked up among the actions of overlays.
+    pub fn press_key(&mut self, key: KeyPress) -> Option<(Action, Option<usize>)> {
+        let mode = match self.overlay {
+            Some(_) => Mode::Overlay,
+            None => Mode::Reader,
+        };
+        if key.key == Key::Esc && (!self.pending_keys.is_empty() || self.count.is_some()) {
+            self.pending_keys.clear();
+            self.count = None;
+            return None;
+        }
+        if let Key::Char(digit @ '0'..='9') = key.key {
+            let starts_count = digit != '0' || self.count.is_some();
+            if self.pending_keys.is_empty()
+                && starts_count
+                && !key.ctrl
+                && !key.alt
+                && self.settings.keymap.resolve(&[key], mode) == Resolved::None
+            {
+                let value = digit.to_digit(10).unwrap_or_default() as usize;
+                let count = self.count.unwrap_or_default() * 10 + value;
+                self.count = Some(count.min(MAX_COUNT));
+                return None;
+            }
+        }
+        self.pending_keys.push(key);
+        match self.settings.keymap.resolve(&self.pending_keys, mode) {
+            Resolved::Action(action) => {
+                self.pending_keys.clear();
+                Some((action, self.count.take()))
+            }
+            Resolved::Prefix => None,
+            Resolved::None => {
+                self.pending_keys.clear();
+                self.count = None;
+                None
+            }
+        }
+    }
+
+    /// Runs a movement action `count` times. With a count, `GotoTop` and `GotoBottom` move to
+    /// that line, counting from 1 as in vim. Returns whether the action was a movement.
+    pub fn navigate(&mut self, action: Action, count: Option<usize>) -> bool {
+        let times = count.unwrap_or(1);
+        match action {
+            Action::MoveDown => self.scroll_down(times),
+            Action::MoveUp => self.scroll_up(times),
+            Action::PageDown => self.page_down(times),
+            Action::PageUp => self.page_up(times),
+            Action::HalfPageDown => self.half_page_down(times),
+            Action::HalfPageUp => self.half_page_up(times),
+            Action::GotoTop | Action::GotoBottom if count.is_some() => {
+                self.goto_line(times.saturating_sub(1))
+            }
+            Action::GotoTop => self.goto_top(),
+            Action::GotoBottom => self.goto_bottom(),
+            Action::NextBookmark => self.repeat(times, Self::goto_next_bookmark),
+            Action::PrevBookmark => self.repeat(times, Self::goto_prev_bookmark),
+            Action::NextChapter => self.repeat(times, Self::goto_next_chapter),
+            Action::PrevChapter => self.repeat(times, Self::goto_prev_chapter),
+            Action::NextMatch => self.repeat(times, Self::goto_next_match),
+            Action::PrevMatch => self.repeat(times, Self::goto_prev_match),
+            Action::CursorLeft => self.repeat(times, |state| state.move_cursor(Motion::Left)),
+            Action::CursorRight => self.repeat(times, |state| state.move_cursor(Motion::Right)),
+            Action::WordForward => {
+                self.repeat(times, |state| state.move_cursor(Motion::WordForward))
+            }
+            Action::WordBackward => {
+                self.repeat(times, |state| state.move_cursor(Motion::WordBackward))
+            }
+            Action::WordEnd => self.repeat(times, |state| state.move_cursor(Motion::WordEnd)),
+            _ => return false,
+        }
+        true
+    }
+
+    fn repeat(&mut self, times: usize, step: impl Fn(&mut Self)) {
+        for _ in 0..times {
+            step(self);
         }
     }
 
@@ -197,24 +1110,116 @@ impl State {
     pub fn resize_screen(&mut self, screen_width: usize, screen_height: usize) {
         self.screen_width = screen_width;
         self.screen_height = screen_height;
+        self.reflow();
         self.pad_left = (self.screen_width / 2).saturating_sub(self.book.line_width / 2);
         self.update_screen();
     }
 
-    pub fn move_up(&mut self) {
-        if self.line_number > 0 {
-            self.line_number -= 1;
-            self.update_screen();
+    /// Wraps the book to the configured line width, limited by the width of the screen,
+    /// keeping the current line in view.
+    pub fn reflow(&mut self) {
+        let mut line_width = self.line_width();
+        if self.screen_width > 0 {
+            line_width = line_width.min(self.screen_width);
+        }
+        if line_width == self.book.line_width {
+            return;
         }
+        let offset = self.book.offset_at(self.line_number, 0);
+        self.book.reflow(line_width);
+        self.line_number = self.book.line_at(offset);
+        self.selection = None;
+        self.definition = None;
+        self.cursor = None;
+        self.visual = None;
+        if let Some(search) = &mut self.search {
+            search.matches = Search::find(&self.book.lines, &search.query);
+            search.current = None;
+        }
+        self.update_screen();
+    }
+
+    pub fn set_line_width(&mut self, line_width: usize) -> anyhow::Result<()> {
+        let line_width = line_width.max(MIN_LINE_WIDTH);
+        self.config.line_width = Some(line_width);
+        self.config.write()?;
+        self.reflow();
+        self.pad_left = (self.screen_width / 2).saturating_sub(self.book.line_width / 2);
+        self.show_message(&format!("(i) Set line width to {line_width}"));
+        Ok(())
+    }
+
+    /// Returns the line width chosen for the book, or else the one from the settings.
+    pub fn line_width(&self) -> usize {
+        self.config
+            .line_width
+            .or(self.settings.line_width)
+            .unwrap_or(DEFAULT_LINE_WIDTH)
+    }
+
+    pub fn increase_line_width(&mut self) -> anyhow::Result<()> {
+        self.set_line_width(self.line_width() + 5)
+    }
+
+    pub fn decrease_line_width(&mut self) -> anyhow::Result<()> {
+        self.set_line_width(self.line_width().saturating_sub(5))
     }
 
-    pub fn move_down(&mut self) {
-        if self.line_number < self.book.line_count.saturating_sub(1) {
-            self.line_number += 1;
+    /// Moves to a line, stopping at the last one.
+    pub fn goto_line(&mut self, line_number: usize) {
+        let line_number = line_number.min(self.book.line_count.saturating_sub(1));
+        if line_number != self.line_number {
+            self.line_number = line_number;
             self.update_screen();
         }
     }
 
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.goto_line(self.line_number.saturating_add(lines));
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.goto_line(self.line_number.saturating_sub(lines));
+    }
+
+    /// Returns the number of lines a page scrolls by, keeping two lines of the previous page
+    /// in view.
+    pub fn page_height(&self) -> usize {
+        self.screen_height.saturating_sub(2).max(1)
+    }
+
+    pub fn page_down(&mut self, pages: usize) {
+        self.scroll_down(self.page_height().saturating_mul(pages));
+    }
+
+    pub fn page_up(&mut self, pages: usize) {
+        self.scroll_up(self.page_height().saturating_mul(pages));
+    }
+
+    pub fn half_page_down(&mut self, count: usize) {
+        self.scroll_down((self.screen_height / 2).max(1).saturating_mul(count));
+    }
+
+    pub fn half_page_up(&mut self, count: usize) {
+        self.scroll_up((self.screen_height / 2).max(1).saturating_mul(count));
+    }
+
+    /// Scrolls down by a notch of the mouse wheel, moving through the entries of an overlay.
+    pub fn wheel_down(&mut self) {
+        match self.overlay {
+            Some(_) => self.repeat(WHEEL_LINES, Self::overlay_down),
+            None => self.scroll_down(WHEEL_LINES),
+        }
+    }
+
+    /// Scrolls up by a notch of the mouse wheel, moving through the entries of an overlay.
+    pub fn wheel_up(&mut self) {
+        match self.overlay {
+            Some(_) => self.repeat(WHEEL_LINES, Self::overlay_up),
+            None => self.scroll_up(WHEEL_LINES),
+        }
+    }
+
     pub fn goto_top(&mut self) {
         if self.line_number > 0 {
             self.line_number = 0;
@@ -229,10 +1234,52 @@ impl State {
         }
     }
 
+    /// Moves to the last reading position, or to the first bookmark if there is none.
+    pub fn restore_position(&mut self) {
+        match &self.config.position {
+            Some(position) => {
+                self.line_number = self.book.line_at(position.offset);
+                self.update_screen();
+            }
+            None => self.goto_next_bookmark(),
+        }
+    }
+
+    pub fn save_position(&mut self) -> anyhow::Result<()> {
+        let offset = self.book.offset_at(self.line_number, 0);
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        self.config.position = Some(self.book.anchor(offset));
+        self.config.last_read = Some(timestamp);
+        self.config.write()?;
+        self.position_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Saves the reading position if it changed and was not saved for a while.
+    pub fn save_position_periodically(&mut self) -> anyhow::Result<()> {
+        if self.position_saved.elapsed() < POSITION_INTERVAL {
+            return Ok(());
+        }
+        let offset = self.book.offset_at(self.line_number, 0);
+        if self
+            .config
+            .position
+            .as_ref()
+            .is_some_and(|position| position.offset == offset)
+        {
+            self.position_saved = Instant::now();
+            return Ok(());
+        }
+        self.save_position()
+    }
+
     pub fn goto_next_bookmark(&mut self) {
         for bookmark in &self.config.bookmarks {
-            if bookmark > &self.line_number {
-                self.line_number = *bookmark;
+            let line_number = self.book.line_at(bookmark.offset);
+            if line_number > self.line_number {
+                self.line_number = line_number;
                 self.update_screen();
                 break;
             }
@@ -241,31 +1288,662 @@ impl State {
 
     pub fn goto_prev_bookmark(&mut self) {
         for bookmark in self.config.bookmarks.iter().rev() {
-            if bookmark < &self.line_number {
-                self.line_number = *bookmark;
+            let line_number = self.book.line_at(bookmark.offset);
+            if line_number < self.line_number {
+                self.line_number = line_number;
                 self.update_screen();
                 break;
             }
         }
     }
 
-    pub fn get_text(&self, (pos, start, end): (usize, usize, usize)) -> Option<String> {
-        let line = self.book.lines.get(pos)?;
-        let text = line.get(start..end)?.to_string();
-        Some(text)
+    /// Returns the char ranges of the given line covered by markers, with the code of their
+    /// color.
+    pub fn markers_at(&self, line_number: usize) -> Vec<(usize, usize, char)> {
+        let line_length = match self.book.lines.get(line_number) {
+            Some(line) => line.chars().count(),
+            None => return Vec::new(),
+        };
+        let mut ranges = Vec::new();
+        for marker in &self.config.markers {
+            let (start_line, start) = self.book.position_at(marker.anchor.offset);
+            let (end_line, end) = self.book.position_at(marker.anchor.offset + marker.length);
+            if start_line > line_number || end_line < line_number {
+                continue;
+            }
+            ranges.push((
+                if start_line == line_number { start } else { 0 },
+                if end_line == line_number {
+                    end
+                } else {
+                    line_length
+                },
+                marker.color.code(),
+            ));
+        }
+        ranges
+    }
+
+    pub fn current_chapter(&self) -> Option<&str> {
+        let index = self.book.chapter_at(self.line_number)?;
+        self.book.toc.get(index).map(|(title, _)| title.as_str())
     }
 
-    pub fn set_selection(&mut self, selection: (usize, usize, usize)) {
-        if !self
-            .selection
-            .is_some_and(|sel| sel.0 == selection.0 && sel.1 == selection.1 && sel.2 == selection.2)
+    pub fn goto_next_chapter(&mut self) {
+        if let Some((_, line)) = self
+            .book
+            .toc
+            .iter()
+            .find(|(_, line)| line > &self.line_number)
         {
-            self.selection = Some(selection);
+            self.line_number = *line;
             self.update_screen();
         }
     }
 
-    pub fn get_selection(&mut self) -> Option<(usize, usize, usize)> {
+    pub fn goto_prev_chapter(&mut self) {
+        if let Some((_, line)) = self
+            .book
+            .toc
+            .iter()
+            .rev()
+            .find(|(_, line)| line < &self.line_number)
+        {
+            self.line_number = *line;
+            self.update_screen();
+        }
+    }
+
+    pub fn open_contents(&mut self) {
+        if self.book.toc.is_empty() {
+            self.show_message("(i) No chapters found");
+            return;
+        }
+        let index = self.book.chapter_at(self.line_number).unwrap_or_default();
+        self.overlay = Some(Overlay::Contents(index));
+        self.update_screen();
+    }
+
+    pub fn close_overlay(&mut self) {
+        if self.overlay.is_some() {
+            self.overlay = None;
+            self.update_screen();
+        }
+    }
+
+    fn overlay_len(&self) -> usize {
+        match self.overlay {
+            Some(Overlay::Contents(_)) => self.book.toc.len(),
+            Some(Overlay::Markers(_)) => self.config.markers.len(),
+            Some(Overlay::Vocabulary(_)) => self.vocabulary.entries.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn overlay_down(&mut self) {
+        let len = self.overlay_len();
+        match &mut self.overlay {
+            Some(
+                Overlay::Contents(index) | Overlay::Markers(index) | Overlay::Vocabulary(index),
+            ) if *index + 1 < len => *index += 1,
+            _ => return,
+        }
+        self.update_screen();
+    }
+
+    pub fn overlay_up(&mut self) {
+        match &mut self.overlay {
+            Some(
+                Overlay::Contents(index) | Overlay::Markers(index) | Overlay::Vocabulary(index),
+            ) if *index > 0 => *index -= 1,
+            _ => return,
+        }
+        self.update_screen();
+    }
+
+    /// Returns the entries of a list overlay as `(label, line)` pairs.
+    pub fn overlay_entries(&self) -> Vec<(String, usize)> {
+        match self.overlay {
+            Some(Overlay::Contents(_)) => self.book.toc.clone(),
+            Some(Overlay::Markers(_)) => self
+                .config
+                .markers
+                .iter()
+                .map(|marker| {
+                    let mut label = self.book.text_at(marker.anchor.offset, marker.length);
+                    if marker.note.is_some() {
+                        label.push_str(" [note]");
+                    }
+                    (label, self.book.line_at(marker.anchor.offset))
+                })
+                .collect(),
+            Some(Overlay::Vocabulary(_)) => self
+                .vocabulary
+                .entries
+                .iter()
+                .map(|entry| {
+                    let sense = entry
+                        .definition
+                        .meanings
+                        .iter()
+                        .flat_map(|meaning| &meaning.senses)
+                        .next()
+                        .map(|sense| sense.definition.as_str())
+                        .unwrap_or_default();
+                    (format!("{} - {sense}", entry.word), entry.line)
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Jumps to the selected overlay entry and closes the overlay.
+    pub fn select_overlay(&mut self) {
+        let line = match self.overlay.take() {
+            Some(Overlay::Contents(index)) => self.book.toc.get(index).map(|(_, line)| *line),
+            Some(Overlay::Markers(index) | Overlay::Note(index)) => self
+                .config
+                .markers
+                .get(index)
+                .map(|marker| self.book.line_at(marker.anchor.offset)),
+            Some(Overlay::Vocabulary(index)) => match self.vocabulary.entries.get(index) {
+                Some(entry) if entry.book == self.book.title => self
+                    .book
+                    .relocate(&entry.anchor)
+                    .map(|offset| self.book.line_at(offset)),
+                Some(entry) => {
+                    self.show_message(&format!("(i) '{}' is from {}", entry.word, entry.book));
+                    None
+                }
+                None => None,
+            },
+            None => return,
+        };
+        if let Some(line) = line {
+            self.line_number = line;
+        }
+        self.update_screen();
+    }
+
+    /// Returns the offsets into the normalized text covered by the selection.
+    fn selection_range(&self) -> Option<(usize, usize)> {
+        let Selection { start, end } = self.selection?;
+        Some((
+            self.book.offset_at(start.0, start.1),
+            self.book.offset_at(end.0, end.1),
+        ))
+    }
+
+    /// Returns the index of the first marker overlapping the selection.
+    pub fn marker_at_selection(&self) -> Option<usize> {
+        let (start, end) = self.selection_range()?;
+        self.config.markers.iter().position(|marker| {
+            marker.anchor.offset < end.max(start + 1)
+                && start < marker.anchor.offset + marker.length
+        })
+    }
+
+    /// Removes the marker under the selection or highlights the selection.
+    pub fn toggle_marker(&mut self) -> anyhow::Result<()> {
+        if let Some(index) = self.marker_at_selection() {
+            self.config.markers.remove(index);
+            self.config.write()?;
+            self.show_message("(i) Removed highlight");
+            return Ok(());
+        }
+        self.add_marker()?;
+        Ok(())
+    }
+
+    fn add_marker(&mut self) -> anyhow::Result<Option<usize>> {
+        let (start, end) = match self.selection_range() {
+            Some(range) if range.1 > range.0 => range,
+            _ => {
+                self.show_message("(i) No selection found");
+                return Ok(None);
+            }
+        };
+        let marker = Marker {
+            anchor: self.book.anchor(start),
+            length: end - start,
+            color: self.marker_color,
+            note: None,
+        };
+        let index = self
+            .config
+            .markers
+            .partition_point(|item| item.anchor.offset <= start);
+        self.config.markers.insert(index, marker);
+        self.config.write()?;
+        self.show_message("(i) Added highlight");
+        Ok(Some(index))
+    }
+
+    /// Switches to the next highlight color, recoloring the marker under the selection.
+    pub fn cycle_marker_color(&mut self) -> anyhow::Result<()> {
+        self.marker_color = self.marker_color.next();
+        if let Some(index) = self.marker_at_selection() {
+            self.config.markers[index].color = self.marker_color;
+            self.config.write()?;
+        }
+        self.show_message(&format!("(i) Highlight color: {}", self.marker_color));
+        Ok(())
+    }
+
+    pub fn open_markers(&mut self) {
+        if self.config.markers.is_empty() {
+            self.show_message("(i) No highlights found");
+            return;
+        }
+        let offset = self.book.offset_at(self.line_number, 0);
+        let index = self
+            .config
+            .markers
+            .partition_point(|marker| marker.anchor.offset < offset)
+            .min(self.config.markers.len() - 1);
+        self.overlay = Some(Overlay::Markers(index));
+        self.update_screen();
+    }
+
+    /// Opens the note of the marker under the selection, highlighting the selection first if
+    /// needed.
+    pub fn open_note(&mut self) -> anyhow::Result<()> {
+        let index = match self.marker_at_selection() {
+            Some(index) => index,
+            None => match self.add_marker()? {
+                Some(index) => index,
+                None => return Ok(()),
+            },
+        };
+        self.overlay = Some(Overlay::Note(index));
+        self.update_screen();
+        Ok(())
+    }
+
+    /// Returns the marker shown by the current overlay.
+    pub fn overlay_marker(&self) -> Option<&Marker> {
+        match self.overlay {
+            Some(Overlay::Markers(index) | Overlay::Note(index)) => self.config.markers.get(index),
+            _ => None,
+        }
+    }
+
+    /// Returns the text to copy: the highlight open in an overlay with its citation, the
+    /// selection, or else the focus line.
+    pub fn yank_text(&self) -> Option<String> {
+        if let Some(marker) = self.overlay_marker() {
+            return Some(self.cite(marker));
+        }
+        let text = match self.selection {
+            Some(selection) => self.get_text(selection)?,
+            None => {
+                let start = self.book.offset_at(self.line_number, 0);
+                let end = self
+                    .book
+                    .line_offsets
+                    .get(self.line_number + 1)
+                    .copied()
+                    .unwrap_or(self.book.text.len());
+                self.book.text_at(start, end.saturating_sub(start))
+            }
+        };
+        Some(text.trim().to_string()).filter(|text| !text.is_empty())
+    }
+
+    /// Quotes a highlight followed by the author, title and chapter it is from.
+    pub fn cite(&self, marker: &Marker) -> String {
+        let text = self.book.text_at(marker.anchor.offset, marker.length);
+        let chapter = self
+            .book
+            .chapter_at(self.book.line_at(marker.anchor.offset))
+            .and_then(|index| self.book.toc.get(index))
+            .map(|(title, _)| title.as_str());
+        let source = [
+            self.book.author.as_deref(),
+            Some(self.book.title.as_str()),
+            chapter,
+        ];
+        let source = source.into_iter().flatten().collect::<Vec<_>>();
+        format!("\"{}\"\n— {}", text.trim(), source.join(", "))
+    }
+
+    pub fn open_overlay_note(&mut self) {
+        if let Some(Overlay::Markers(index)) = self.overlay {
+            self.overlay = Some(Overlay::Note(index));
+            self.update_screen();
+        }
+    }
+
+    pub fn set_note(&mut self, note: &str) -> anyhow::Result<()> {
+        let index = match self.overlay {
+            Some(Overlay::Markers(index) | Overlay::Note(index)) => index,
+            _ => return Ok(()),
+        };
+        if let Some(marker) = self.config.markers.get_mut(index) {
+            let note = note.trim();
+            marker.note = (!note.is_empty()).then(|| note.to_string());
+            self.config.write()?;
+            self.show_message("(i) Saved note");
+        }
+        Ok(())
+    }
+
+    /// Removes the marker shown by the current overlay.
+    pub fn remove_overlay_marker(&mut self) -> anyhow::Result<()> {
+        let index = match self.overlay {
+            Some(Overlay::Markers(index) | Overlay::Note(index)) => index,
+            _ => return Ok(()),
+        };
+        if index < self.config.markers.len() {
+            self.config.markers.remove(index);
+            self.config.write()?;
+            self.show_message("(i) Removed highlight");
+        }
+        self.overlay = match self.overlay {
+            Some(Overlay::Markers(_)) if !self.config.markers.is_empty() => {
+                Some(Overlay::Markers(index.min(self.config.markers.len() - 1)))
+            }
+            _ => None,
+        };
+        self.update_screen();
+        Ok(())
+    }
+
+    pub fn open_vocabulary(&mut self) -> anyhow::Result<()> {
+        self.vocabulary = Vocabulary::load()?;
+        if self.vocabulary.entries.is_empty() {
+            self.show_message("(i) No words looked up yet");
+            return Ok(());
+        }
+        self.overlay = Some(Overlay::Vocabulary(self.vocabulary.entries.len() - 1));
+        self.update_screen();
+        Ok(())
+    }
+
+    pub fn remove_vocabulary_entry(&mut self) -> anyhow::Result<()> {
+        let index = match self.overlay {
+            Some(Overlay::Vocabulary(index)) => index,
+            _ => return Ok(()),
+        };
+        if index < self.vocabulary.entries.len() {
+            let entry = self.vocabulary.entries.remove(index);
+            self.vocabulary.save()?;
+            self.show_message(&format!("(i) Removed '{}' from vocabulary", entry.word));
+        }
+        self.overlay = match self.vocabulary.entries.len() {
+            0 => None,
+            len => Some(Overlay::Vocabulary(index.min(len - 1))),
+        };
+        self.update_screen();
+        Ok(())
+    }
+
+    /// Records a looked up word with the sentence it was found in.
+    fn record_word(
+        &mut self,
+        word: &str,
+        selected: &str,
+        selection: Selection,
+        definition: &Definition,
+    ) -> anyhow::Result<()> {
+        let (line, start) = selection.start;
+        let offset = self.book.offset_at(line, start);
+        let added_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let mut vocabulary = Vocabulary::load()?;
+        let added = vocabulary.add(VocabularyEntry {
+            word: word.to_string(),
+            selected: selected.to_string(),
+            sentence: self.book.sentence_at(offset),
+            book: self.book.title.to_string(),
+            line,
+            anchor: self.book.anchor(offset),
+            definition: definition.clone(),
+            added_at,
+            review: Review::default(),
+        });
+        if added {
+            vocabulary.save()?;
+        }
+        self.vocabulary = vocabulary;
+        Ok(())
+    }
+
+    /// Moves the keyboard cursor, placing it on the first word of the focus line if it is not
+    /// shown yet. Moving past the words of a line continues on the next line with words.
+    pub fn move_cursor(&mut self, motion: Motion) {
+        let (line_number, index) = match self.cursor {
+            Some(cursor) => cursor,
+            None => {
+                let index = self
+                    .book
+                    .lines
+                    .get(self.line_number)
+                    .and_then(|line| word_ranges(line).first().map(|(start, _)| *start))
+                    .unwrap_or_default();
+                self.cursor = Some((self.line_number, index));
+                self.update_cursor_selection();
+                return;
+            }
+        };
+        let graphemes = self.book.graphemes(line_number);
+        let words = word_ranges(&self.book.lines[line_number]);
+        // the cursor moves by graphemes and rests on their first char
+        let last_char = |end: usize| {
+            self.book
+                .grapheme_at(line_number, end - 1)
+                .map_or(end - 1, |(start, _)| start)
+        };
+        let cursor = match motion {
+            Motion::Left => graphemes
+                .iter()
+                .rev()
+                .find(|grapheme| grapheme.start < index)
+                .map(|grapheme| (line_number, grapheme.start)),
+            Motion::Right => graphemes
+                .iter()
+                .find(|grapheme| grapheme.start > index)
+                .map(|grapheme| (line_number, grapheme.start)),
+            Motion::WordForward => words
+                .iter()
+                .find(|(start, _)| *start > index)
+                .map(|(start, _)| (line_number, *start))
+                .or_else(|| self.find_word(line_number, true, |(start, _)| start)),
+            Motion::WordBackward => words
+                .iter()
+                .rev()
+                .find(|(start, _)| *start < index)
+                .map(|(start, _)| (line_number, *start))
+                .or_else(|| self.find_word(line_number, false, |(start, _)| start)),
+            Motion::WordEnd => words
+                .iter()
+                .find(|(_, end)| last_char(*end) > index)
+                .map(|(_, end)| (line_number, last_char(*end)))
+                .or_else(|| {
+                    let (line, end) = self.find_word(line_number, true, |(_, end)| end)?;
+                    let start = self.book.grapheme_at(line, end - 1)?.0;
+                    Some((line, start))
+                }),
+        };
+        if let Some(cursor) = cursor {
+            self.cursor = Some(cursor);
+            if cursor.0 != self.line_number {
+                self.line_number = cursor.0;
+                self.update_screen();
+            }
+            self.update_cursor_selection();
+        }
+    }
+
+    /// Returns the position of the first word on the lines after (or last word on the lines
+    /// before) the given line, using `position` to pick the start or end of the word.
+    fn find_word(
+        &self,
+        line_number: usize,
+        forward: bool,
+        position: impl Fn((usize, usize)) -> usize,
+    ) -> Option<(usize, usize)> {
+        let word = |line: usize| {
+            let words = word_ranges(&self.book.lines[line]);
+            let word = if forward { words.first() } else { words.last() };
+            word.map(|word| (line, position(*word)))
+        };
+        if forward {
+            (line_number + 1..self.book.lines.len()).find_map(word)
+        } else {
+            (0..line_number).rev().find_map(word)
+        }
+    }
+
+    /// Keeps the cursor on the focus line after scrolling, at the word closest to its column.
+    pub fn sync_cursor(&mut self) {
+        let (line_number, index) = match self.cursor {
+            Some(cursor) if cursor.0 != self.line_number => cursor,
+            _ => return,
+        };
+        let column = self.book.column_at_index(line_number, index);
+        let index = word_ranges(&self.book.lines[self.line_number])
+            .into_iter()
+            .min_by_key(|(start, end)| {
+                let start_column = self.book.column_at_index(self.line_number, *start);
+                let end_column = self.book.column_at_index(self.line_number, *end);
+                column
+                    .abs_diff(start_column)
+                    .min(column.abs_diff(end_column))
+            })
+            .map(|(start, _)| start)
+            .unwrap_or_default();
+        self.cursor = Some((self.line_number, index));
+        self.update_cursor_selection();
+    }
+
+    /// Starts a visual selection at the cursor, or ends it keeping the selected text.
+    pub fn toggle_visual(&mut self) {
+        if self.visual.take().is_some() {
+            self.clear_message();
+            return;
+        }
+        if self.cursor.is_none() {
+            self.move_cursor(Motion::Right);
+        }
+        self.visual = self.cursor;
+        self.update_cursor_selection();
+        self.show_message("(i) Visual selection");
+    }
+
+    pub fn clear_cursor(&mut self) {
+        if self.cursor.is_some() || self.visual.is_some() {
+            self.cursor = None;
+            self.visual = None;
+            self.update_screen();
+        }
+    }
+
+    /// Selects the word under the cursor, or the text between the start of the visual
+    /// selection and the cursor.
+    fn update_cursor_selection(&mut self) {
+        let (line_number, index) = match self.cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        if let Some(start) = self.visual {
+            self.select_between(start, (line_number, index));
+            return;
+        }
+        let selection = word_ranges(&self.book.lines[line_number])
+            .into_iter()
+            .find(|(start, end)| (*start..*end).contains(&index))
+            .or_else(|| self.book.grapheme_at(line_number, index));
+        match selection {
+            Some((start, end)) => self.set_selection(Selection::line(line_number, start, end)),
+            None => self.clear_selection(),
+        }
+        self.update_screen();
+    }
+
+    /// Selects the text between two positions given in any order, both included. Positions
+    /// within a word extend the selection to the whole word.
+    pub fn select_between(&mut self, a: (usize, usize), b: (usize, usize)) {
+        let (start, end) = (a.min(b), a.max(b));
+        let word_at = |(line_number, index): (usize, usize)| {
+            let line = self.book.lines.get(line_number)?;
+            word_ranges(line)
+                .into_iter()
+                .find(|(start, end)| (*start..*end).contains(&index))
+        };
+        let grapheme_at = |(line_number, index): (usize, usize)| {
+            self.book
+                .grapheme_at(line_number, index)
+                .unwrap_or((index, index))
+        };
+        let start_index = word_at(start).unwrap_or(grapheme_at(start)).0;
+        let end_index = word_at(end).unwrap_or(grapheme_at(end)).1;
+        self.set_selection(Selection::new((start.0, start_index), (end.0, end_index)));
+    }
+
+    /// Starts a mouse selection at a position.
+    pub fn start_drag(&mut self, position: (usize, usize)) {
+        self.clear_cursor();
+        self.drag = Some(position);
+    }
+
+    /// Extends the mouse selection to a position. A click without dragging selects the word
+    /// under the mouse.
+    pub fn update_drag(&mut self, position: (usize, usize), released: bool) {
+        let start = match self.drag {
+            Some(start) => start,
+            None => return,
+        };
+        if released {
+            self.drag = None;
+        }
+        if released && start == position {
+            self.select_word(position);
+            return;
+        }
+        self.select_between(start, position);
+    }
+
+    /// Extends the current selection to a position, keeping its start.
+    pub fn extend_selection(&mut self, position: (usize, usize)) {
+        match self.selection {
+            Some(selection) => self.select_between(selection.start, position),
+            None => self.select_word(position),
+        }
+    }
+
+    /// Selects the word at a position, doing nothing between words.
+    pub fn select_word(&mut self, (line_number, index): (usize, usize)) {
+        let word = self.book.lines.get(line_number).and_then(|line| {
+            word_ranges(line)
+                .into_iter()
+                .find(|(start, end)| (*start..*end).contains(&index))
+        });
+        if let Some((start, end)) = word {
+            self.set_selection(Selection::line(line_number, start, end));
+        }
+    }
+
+    /// Returns the text of a selection without formatting, joining lines with spaces.
+    pub fn get_text(&self, selection: Selection) -> Option<String> {
+        let Selection { start, end } = selection;
+        if start.0 >= self.book.lines.len() {
+            return None;
+        }
+        let start = self.book.offset_at(start.0, start.1);
+        let end = self.book.offset_at(end.0, end.1);
+        Some(self.book.text_at(start, end.saturating_sub(start)))
+    }
+
+    pub fn set_selection(&mut self, selection: Selection) {
+        if self.selection != Some(selection) {
+            self.selection = Some(selection);
+            self.update_screen();
+        }
+    }
+
+    pub fn get_selection(&mut self) -> Option<Selection> {
         self.selection
     }
 
@@ -290,15 +1968,48 @@ impl State {
         }
     }
 
+    /// Returns whether focus mode is on for the book, or else in the settings.
+    pub fn focus_mode(&self) -> bool {
+        self.config
+            .focus_mode
+            .or(self.settings.focus_mode)
+            .unwrap_or_default()
+    }
+
     pub fn toggle_focus_mode(&mut self) -> anyhow::Result<()> {
-        self.config.focus_mode = Some(!self.config.focus_mode.unwrap_or_default());
-        self.config.write(&self.path)?;
+        self.config.focus_mode = Some(!self.focus_mode());
+        self.config.write()?;
         self.show_message("(i) Toggled focus mode");
         self.update_screen();
         Ok(())
     }
 
+    /// Switches this book to the next theme. Coming back to the theme of the settings removes
+    /// the choice from the book, so that it follows the settings again.
+    pub fn cycle_theme(&mut self) -> anyhow::Result<()> {
+        let names = self.settings.theme_names();
+        let default = self.settings.default_theme_name();
+        let current = self
+            .config
+            .theme
+            .as_ref()
+            .and_then(|name| names.iter().position(|other| other == name))
+            .or_else(|| names.iter().position(|other| other == &default))
+            .unwrap_or_default();
+        let name = names[(current + 1) % names.len()].clone();
+        self.theme = self.settings.theme_or_default(Some(&name));
+        if name == default {
+            self.config.theme = None;
+            self.show_message(&format!("(i) Theme: {name} (default)"));
+        } else {
+            self.config.theme = Some(name.clone());
+            self.show_message(&format!("(i) Theme: {name}"));
+        }
+        self.config.write()
+    }
+
     pub fn toggle_bookmark(&mut self, line_number: usize) -> anyhow::Result<()> {
+        let line_number = self.bookmark_line(line_number);
         if self.has_bookmark(line_number) {
             self.remove_bookmark(line_number)?;
         } else {
@@ -307,23 +2018,31 @@ impl State {
         Ok(())
     }
 
-    pub fn has_bookmark(&mut self, line_number: usize) -> bool {
+    pub fn has_bookmark(&self, line_number: usize) -> bool {
         self.config
             .bookmarks
             .iter()
-            .any(|item| item == &line_number)
+            .any(|item| self.book.line_at(item.offset) == line_number)
+    }
+
+    /// Returns the line a bookmark on the given line is anchored to. Blank lines have no text
+    /// of their own, so their bookmarks belong to the next line with text.
+    fn bookmark_line(&self, line_number: usize) -> usize {
+        self.book.line_at(self.book.offset_at(line_number, 0))
     }
 
     pub fn add_bookmark(&mut self, line_number: usize) -> anyhow::Result<()> {
-        if !self
+        let line_number = self.bookmark_line(line_number);
+        let offset = self.book.offset_at(line_number, 0);
+        let exists = self
             .config
             .bookmarks
             .iter()
-            .any(|item| item == &line_number)
-        {
-            self.config.bookmarks.push(line_number);
-            self.config.bookmarks.sort();
-            self.config.write(&self.path)?;
+            .any(|item| item.offset == offset);
+        if !exists && !self.has_bookmark(line_number) {
+            self.config.bookmarks.push(self.book.anchor(offset));
+            self.config.bookmarks.sort_by_key(|item| item.offset);
+            self.config.write()?;
             self.show_message("(i) Added bookmark");
             self.update_screen();
         }
@@ -331,48 +2050,244 @@ impl State {
     }
 
     pub fn remove_bookmark(&mut self, line_number: usize) -> anyhow::Result<()> {
+        let line_number = self.bookmark_line(line_number);
         if let Some(index) = self
             .config
             .bookmarks
             .iter()
-            .position(|item| item == &line_number)
+            .position(|item| self.book.line_at(item.offset) == line_number)
         {
             self.config.bookmarks.remove(index);
-            self.config.write(&self.path)?;
+            self.config.write()?;
             self.show_message("(i) Removed bookmark");
             self.update_screen();
         }
         Ok(())
     }
 
-    pub async fn define_selection(&mut self) -> anyhow::Result<()> {
+    /// Starts looking up the selected word in the background, replacing any pending lookup.
+    pub async fn define_selection(&mut self) {
         let selection = match self.selection {
             Some(selection) => selection,
             None => {
                 self.show_message("(i) No selection found");
-                return Ok(());
+                return;
             }
         };
-        let text = match self.get_text(selection) {
-            Some(text) => text,
-            None => {
+        let word = match self.get_text(selection) {
+            Some(text) if !text.trim().is_empty() => text,
+            _ => {
                 self.show_message("(i) No text at specified selection");
-                return Ok(());
+                return;
             }
         };
-        let url = format!("https://api.dictionaryapi.dev/api/v2/entries/en/{text}");
-        let res = reqwest::get(url).await?;
-        let result: serde_json::Value = res.json().await?;
-        let definition = match Definition::from_json(&result) {
-            Some(definition) => definition,
-            None => {
-                self.show_message("(i) No definition found");
-                return Ok(());
+        if let Some(lookup) = self.lookup.take() {
+            lookup.task.cancel().await;
+        }
+        let dictionary = Arc::clone(&self.dictionary);
+        let timeout = self.lookup_timeout;
+        let (sender, receiver) = mpsc::channel();
+        let task = task::spawn({
+            let word = word.clone();
+            async move {
+                let lookup = lemma::define(dictionary.as_ref(), &word);
+                let result = async_std::future::timeout(timeout, lookup)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(anyhow::anyhow!("timed out after {}s", timeout.as_secs()))
+                    });
+                // the receiver is gone if the lookup was cancelled
+                let _ = sender.send(result);
+            }
+        });
+        self.show_message(&format!("(i) Looking up '{word}'... (Esc to cancel)"));
+        self.lookup = Some(PendingLookup {
+            selection,
+            word,
+            started: Instant::now(),
+            task,
+            receiver,
+        });
+    }
+
+    /// Checks whether the pending lookup has finished and shows its result.
+    pub fn poll_lookup(&mut self) {
+        let lookup = match &self.lookup {
+            Some(lookup) => lookup,
+            None => return,
+        };
+        let result = match lookup.receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => {
+                let elapsed = lookup.started.elapsed().as_secs();
+                let message = format!(
+                    "(i) Looking up '{}'... {elapsed}s (Esc to cancel)",
+                    lookup.word
+                );
+                if self.message.as_deref() != Some(message.as_str()) {
+                    self.show_message(&message);
+                }
+                return;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("lookup task stopped unexpectedly"))
             }
         };
-        self.definition = Some((selection, definition));
+        let lookup = self.lookup.take().unwrap();
+        match result {
+            Ok(Some((form, definition))) => {
+                if form.to_lowercase() == lookup.word.to_lowercase() {
+                    self.clear_message();
+                } else {
+                    self.show_message(&format!("(i) Showing '{form}' for '{}'", lookup.word));
+                }
+                if let Err(err) =
+                    self.record_word(&form, &lookup.word, lookup.selection, &definition)
+                {
+                    self.show_message(&format!("(!) Could not save vocabulary: {err}"));
+                }
+                self.definition = Some((lookup.selection, definition));
+                self.update_screen();
+            }
+            Ok(None) => {
+                self.show_message(&format!("(i) No definition found for '{}'", lookup.word))
+            }
+            Err(err) => self.show_message(&format!("(!) Lookup failed: {err}")),
+        }
+    }
+
+    pub async fn cancel_lookup(&mut self) {
+        if let Some(lookup) = self.lookup.take() {
+            lookup.task.cancel().await;
+            self.show_message("(i) Lookup cancelled");
+        }
+    }
+
+    pub fn start_search(&mut self, backward: bool) {
+        self.search = Some(Search {
+            backward,
+            origin: self.line_number,
+            ..Default::default()
+        });
+        self.update_screen();
+    }
+
+    /// Updates the query of the current search and moves to the first match from the line the
+    /// search was started on.
+    pub fn update_search(&mut self, query: &str) {
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return,
+        };
+        search.query = query.to_string();
+        search.matches = Search::find(&self.book.lines, query);
+        search.current = if search.backward {
+            search
+                .matches
+                .iter()
+                .rposition(|(line, _, _)| line <= &search.origin)
+                .or(search.matches.len().checked_sub(1))
+        } else {
+            search
+                .matches
+                .iter()
+                .position(|(line, _, _)| line >= &search.origin)
+                .or((!search.matches.is_empty()).then_some(0))
+        };
+        self.line_number = match search.current {
+            Some(index) => search.matches[index].0,
+            None => search.origin,
+        };
         self.update_screen();
-        Ok(())
+    }
+
+    pub fn confirm_search(&mut self) {
+        match &self.search {
+            Some(search) if !search.matches.is_empty() => {
+                let count = search.matches.len();
+                self.show_message(&format!("(i) Found {count} matches for '{}'", search.query));
+            }
+            Some(search) => {
+                let message = format!("(i) No matches for '{}'", search.query);
+                self.search = None;
+                self.show_message(&message);
+            }
+            None => (),
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.line_number = search.origin;
+            self.clear_message();
+            self.update_screen();
+        }
+    }
+
+    pub fn clear_search(&mut self) {
+        if self.search.is_some() {
+            self.search = None;
+            self.update_screen();
+        }
+    }
+
+    /// Moves to the next match in the direction of the search.
+    pub fn goto_next_match(&mut self) {
+        match &self.search {
+            Some(search) => self.goto_match(!search.backward),
+            None => self.show_message("(i) No search active"),
+        }
+    }
+
+    /// Moves to the next match against the direction of the search.
+    pub fn goto_prev_match(&mut self) {
+        match &self.search {
+            Some(search) => self.goto_match(search.backward),
+            None => self.show_message("(i) No search active"),
+        }
+    }
+
+    fn goto_match(&mut self, forward: bool) {
+        let search = match &mut self.search {
+            Some(search) if !search.matches.is_empty() => search,
+            _ => return,
+        };
+        // the current match, or the focus line if the reader moved away from it
+        let here = match search.current.and_then(|index| search.matches.get(index)) {
+            Some(&(line, start, _)) if line == self.line_number => (line, start),
+            _ if forward => (self.line_number, usize::MAX),
+            _ => (self.line_number, 0),
+        };
+        let (index, wrapped) = if forward {
+            match search
+                .matches
+                .iter()
+                .position(|&(line, start, _)| (line, start) > here)
+            {
+                Some(index) => (index, false),
+                None => (0, true),
+            }
+        } else {
+            match search
+                .matches
+                .iter()
+                .rposition(|&(line, start, _)| (line, start) < here)
+            {
+                Some(index) => (index, false),
+                None => (search.matches.len() - 1, true),
+            }
+        };
+        let count = search.matches.len();
+        search.current = Some(index);
+        self.line_number = search.matches[index].0;
+        if wrapped {
+            self.show_message(&format!(
+                "(i) Match {} of {count}, search wrapped",
+                index + 1
+            ));
+        } else {
+            self.show_message(&format!("(i) Match {} of {count}", index + 1));
+        }
     }
 
     pub fn show_message(&mut self, message: &str) {
@@ -381,16 +2296,250 @@ impl State {
     }
 }
 
+/// Returns the XDG base directory named by `var`, or `fallback` inside the home directory if
+/// it is not set.
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Some(PathBuf::from(env::var_os("HOME")?).join(fallback)),
+    }
+}
+
+/// Wraps text into lines of at most `width` columns.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    Book::wrap(&text.split_whitespace().collect::<Vec<_>>(), width)
+        .into_iter()
+        .map(|line| line.replace(Codes::SOFT_BREAK, ""))
+        .collect()
+}
+
+/// Returns whether a line ends in the middle of a word broken by wrapping.
+fn has_soft_break(line: &str) -> bool {
+    line.chars()
+        .rev()
+        .take_while(|char| Codes::is_code(*char))
+        .any(|char| char == Codes::SOFT_BREAK)
+}
+
+/// Returns the number of columns the line occupies without formatting codes.
+pub fn text_width(line: &str) -> usize {
+    text::width(line)
+}
+
+fn is_numbered_heading(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let first = words.next().unwrap_or_default().to_uppercase();
+    let first = first.trim_end_matches(['.', ':']);
+    if NAMED_HEADINGS.contains(&first) {
+        return true;
+    }
+    if !NUMBERED_HEADINGS.contains(&first) {
+        return false;
+    }
+    let number = words.next().unwrap_or_default().to_uppercase();
+    let number = number.trim_end_matches(['.', ':', ',']);
+    !number.is_empty()
+        && (number.chars().all(|char| char.is_ascii_digit())
+            || roman_value(number).is_some()
+            || NUMBER_WORDS.contains(&number))
+}
+
+/// Returns the value of a roman numeral in its usual form, like `XIV` but not `IIII`, `IC` or
+/// `CIVIL`.
+fn roman_value(word: &str) -> Option<usize> {
+    let mut value = 0;
+    let mut rest = word;
+    for (number, numeral) in ROMAN_NUMERALS {
+        while let Some(tail) = rest.strip_prefix(numeral) {
+            value += number;
+            rest = tail;
+        }
+    }
+    if !rest.is_empty() || value == 0 {
+        return None;
+    }
+    // numerals are written with the largest possible symbols, which also rules out repeats
+    let mut canonical = String::new();
+    let mut remaining = value;
+    for (number, numeral) in ROMAN_NUMERALS {
+        while remaining >= number {
+            canonical.push_str(numeral);
+            remaining -= number;
+        }
+    }
+    (canonical == word).then_some(value)
+}
+
+fn is_caps_heading(line: &str) -> bool {
+    let letters = line
+        .chars()
+        .filter(|char| char.is_alphabetic())
+        .collect::<Vec<_>>();
+    letters.len() >= 4
+        && letters.iter().all(|char| char.is_uppercase())
+        && line.split_whitespace().count() <= 8
+}
+
+/// Returns the words of a line without formatting codes.
+fn normalize(line: &str) -> Vec<String> {
+    line.split_whitespace()
+        .map(|word| word.chars().filter(|char| !Codes::is_code(*char)).collect())
+        .filter(|word: &String| !word.is_empty())
+        .collect()
+}
+
+/// Returns the column in the normalized line of the char at `index` in the given line.
+fn normalized_column(line: &str, index: usize) -> usize {
+    let mut column = 0;
+    let mut started = false;
+    let mut space = false;
+    for (i, char) in line.chars().enumerate() {
+        if i == index {
+            if space {
+                column += 1;
+            }
+            break;
+        }
+        if Codes::is_code(char) {
+            continue;
+        }
+        if char.is_whitespace() {
+            space = started;
+            continue;
+        }
+        if space {
+            column += 1;
+            space = false;
+        }
+        column += 1;
+        started = true;
+    }
+    column
+}
+
+/// Returns the words of a line as ranges of char indexes. Apostrophes inside a word and marks
+/// combined with its letters are part of it.
+pub fn word_ranges(line: &str) -> Vec<(usize, usize)> {
+    let chars = line.chars().collect::<Vec<_>>();
+    // chars displayed together with the one before them, like combining marks
+    let mut extending = vec![false; chars.len()];
+    for grapheme in text::graphemes(line) {
+        extending[grapheme.start + 1..grapheme.end].fill(true);
+    }
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, char) in chars.iter().enumerate() {
+        let is_word = char.is_alphanumeric()
+            || (extending[i] && start.is_some())
+            || (matches!(char, '\'' | '’')
+                && start.is_some()
+                && chars.get(i + 1).is_some_and(|next| next.is_alphanumeric()));
+        match (is_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(word_start)) => {
+                ranges.push((word_start, i));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(word_start) = start {
+        ranges.push((word_start, chars.len()));
+    }
+    ranges
+}
+
+/// Returns the index of the char in the given line at `column` of the normalized line.
+fn char_index(line: &str, column: usize) -> usize {
+    let mut current = 0;
+    let mut started = false;
+    let mut space = false;
+    let mut count = 0;
+    for (i, char) in line.chars().enumerate() {
+        count = i + 1;
+        if Codes::is_code(char) {
+            continue;
+        }
+        if char.is_whitespace() {
+            if started && !space {
+                if current == column {
+                    return i;
+                }
+                space = true;
+            }
+            continue;
+        }
+        if space {
+            current += 1;
+            space = false;
+        }
+        if current == column {
+            return i;
+        }
+        current += 1;
+        started = true;
+    }
+    count
+}
+
+fn is_blank(line: &str) -> bool {
+    line.chars()
+        .all(|char| char.is_whitespace() || Codes::is_code(char))
+}
+
+/// Closes every style still open at the end of a line and reopens it on the next line,
+/// so that each line can be rendered on its own.
+fn balance_codes(lines: Vec<String>) -> Vec<String> {
+    let styles = [
+        (Codes::BOLD, Codes::RESET_BOLD),
+        (Codes::ITALIC, Codes::RESET_ITALIC),
+        (Codes::UNDERLINE, Codes::RESET_UNDERLINE),
+    ];
+    let mut open = [false; 3];
+    let mut balanced = Vec::new();
+    for line in lines {
+        let mut chars = Vec::new();
+        for (i, (start, _)) in styles.iter().enumerate() {
+            if open[i] && !line.starts_with(*start) && !is_blank(&line) {
+                chars.push(*start);
+            }
+        }
+        for char in line.chars() {
+            for (i, (start, end)) in styles.iter().enumerate() {
+                if char == *start {
+                    open[i] = true;
+                }
+                if char == *end {
+                    open[i] = false;
+                }
+            }
+            chars.push(char);
+        }
+        for (i, (_, end)) in styles.iter().enumerate() {
+            if open[i] && !is_blank(&line) {
+                chars.push(*end);
+            }
+        }
+        balanced.push(chars.iter().collect());
+    }
+    balanced
+}
+
 pub struct Codes;
 
 impl Codes {
     pub const RESET: char = '\u{E000}';
+    // bold
+    pub const BOLD: char = '\u{E001}';
+    pub const RESET_BOLD: char = '\u{E022}';
     // italic
     pub const ITALIC: char = '\u{E003}';
     pub const RESET_ITALIC: char = '\u{E023}';
     // underline
     pub const UNDERLINE: char = '\u{E004}';
     pub const RESET_UNDERLINE: char = '\u{E024}';
+    // ends a line inside a word broken by wrapping
+    pub const SOFT_BREAK: char = '\u{E030}';
     // foreground
     pub const RESET_FOREGROUND: char = '\u{E100}';
     pub const FOREGROUND_DEFAULT: char = '\u{E101}';
@@ -398,4 +2547,699 @@ impl Codes {
     pub const RESET_BACKGROUND: char = '\u{E200}';
     pub const BACKGROUND_MARKER: char = '\u{E201}';
     pub const BACKGROUND_SELECTION: char = '\u{E202}';
+    pub const BACKGROUND_SEARCH: char = '\u{E203}';
+    pub const BACKGROUND_MARKER_GREEN: char = '\u{E204}';
+    pub const BACKGROUND_MARKER_BLUE: char = '\u{E205}';
+    pub const BACKGROUND_MARKER_PINK: char = '\u{E206}';
+
+    pub fn is_code(char: char) -> bool {
+        ('\u{E000}'..='\u{E2FF}').contains(&char)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A book of three chapters with twenty short paragraphs each, on a screen of 20 rows. The
+    /// state has no file, so nothing is written.
+    fn state() -> State {
+        let mut content = String::new();
+        for chapter in ["I", "II", "III"] {
+            content.push_str(&format!("CHAPTER {chapter}\n\n"));
+            for i in 0..20 {
+                content.push_str(&format!("Paragraph {i} of chapter {chapter}.\n\n"));
+            }
+        }
+        let book = Book::new(&content, "Test".to_string(), None);
+        let mut state = State::new("test.txt", Settings::default(), Config::default(), book);
+        state.resize_screen(100, 20);
+        state
+    }
+
+    #[test]
+    fn bookmark_on_blank_line_belongs_to_next_line() {
+        let mut state = state();
+        assert!(state.book.lines[1].is_empty());
+        state.toggle_bookmark(1).unwrap();
+        state.toggle_bookmark(1).unwrap();
+        assert!(state.config.bookmarks.is_empty());
+        state.add_bookmark(1).unwrap();
+        state.add_bookmark(2).unwrap();
+        assert_eq!(state.config.bookmarks.len(), 1);
+        assert!(state.has_bookmark(2));
+        state.remove_bookmark(1).unwrap();
+        assert!(state.config.bookmarks.is_empty());
+    }
+
+    #[test]
+    fn search_lowercases_text_like_query() {
+        let lines = vec!["İstanbul and istanbul".to_string()];
+        assert_eq!(Search::find(&lines, "İSTANBUL"), vec![(0, 0, 8)]);
+        assert_eq!(Search::find(&lines, "and"), vec![(0, 9, 12)]);
+    }
+
+    #[test]
+    fn next_match_stays_on_line_with_more_matches() {
+        let mut state = state();
+        state.start_search(false);
+        state.update_search("ap");
+        state.confirm_search();
+        assert_eq!(state.line_number, 0);
+        state.goto_next_match();
+        assert_eq!(state.line_number, 2);
+        state.goto_next_match();
+        assert_eq!(state.line_number, 2);
+        assert_eq!(state.search.as_ref().unwrap().current, Some(2));
+        state.goto_next_match();
+        assert_eq!(state.line_number, 4);
+        state.goto_prev_match();
+        state.goto_prev_match();
+        assert_eq!(state.search.as_ref().unwrap().current, Some(1));
+    }
+
+    #[test]
+    fn cycling_back_to_default_theme_clears_book_theme() {
+        let mut state = state();
+        state.cycle_theme().unwrap();
+        assert_eq!(state.config.theme.as_deref(), Some("light"));
+        for _ in 1..state.settings.theme_names().len() {
+            state.cycle_theme().unwrap();
+        }
+        assert_eq!(state.config.theme, None);
+        assert_eq!(state.theme.text, Theme::dark().text);
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    const PROSE: &str = "    It was a bright cold day in April, and the clocks were
+striking thirteen. Winston Smith, his chin nuzzled into his
+breast in an effort to escape the vile wind, slipped quickly
+through the glass doors of Victory Mansions | though not
+quickly enough to prevent a swirl of gritty dust from
+entering along with him.";
+
+    const POEM: &str = "Tyger Tyger, burning bright,
+In the forests of the night;
+What immortal hand or eye,
+Could frame thy fearful symmetry?";
+
+    const TABLE: &str = "| Novel      | Year |
+| Emma       | 1815 |
+| Persuasion | 1817 |";
+
+    #[test]
+    fn keeps_only_laid_out_blocks() {
+        assert!(!Book::is_preformatted(&lines(PROSE), 60));
+        assert!(Book::is_preformatted(&lines(POEM), 60));
+        assert!(Book::is_preformatted(&lines(TABLE), 60));
+        let indented = POEM
+            .lines()
+            .map(|line| format!("    {line}"))
+            .collect::<Vec<_>>();
+        assert!(Book::is_preformatted(&indented, 60));
+        assert!(Book::is_preformatted(&lines("        THE END"), 60));
+    }
+
+    #[test]
+    fn reflows_indented_prose_around_poetry_and_tables() {
+        let content = format!("{PROSE}\n\n{POEM}\n\n{TABLE}\n");
+        let mut book = Book::new(&content, "Test".to_string(), None);
+        book.reflow(40 + GUTTER_WIDTH);
+        let blank = book.lines.iter().position(|line| line.is_empty()).unwrap();
+        assert!(book.lines[..blank]
+            .iter()
+            .all(|line| text_width(line) <= 40 && !line.starts_with(' ')));
+        assert_eq!(book.lines[0], "It was a bright cold day in April, and");
+        let words = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert_eq!(words(&book.lines[..blank].join(" ")), words(PROSE));
+        for line in POEM.lines().chain(TABLE.lines()) {
+            assert!(book.lines.iter().any(|item| item == line), "{line}");
+        }
+    }
+
+    #[test]
+    fn reads_roman_numerals_in_their_usual_form() {
+        assert_eq!(roman_value("XIV"), Some(14));
+        assert_eq!(roman_value("MCMXCIV"), Some(1994));
+        assert_eq!(roman_value("MIX"), Some(1009));
+        for word in ["", "IIII", "IC", "VX", "CIVIL", "DID", "MID"] {
+            assert_eq!(roman_value(word), None, "{word}");
+        }
+    }
+
+    #[test]
+    fn detects_chapters_but_not_capitalized_words() {
+        let content = "PREFACE\n\nSome words.\n\n\nCHAPTER I.\nThe Beginning\n\nText.\n\n\n\
+            II.\n\nMore text.\n\nMIX\n\nCIVIL\n\nDID\n\nMID\n\n\nCHAPTER MIX\n\nText.\n\n\n\
+            THE LAST PART\n\nEnd.\n";
+        let book = Book::new(content, "Test".to_string(), None);
+        let titles = book
+            .toc
+            .iter()
+            .map(|(title, _)| title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                "PREFACE",
+                "CHAPTER I. The Beginning",
+                "II.",
+                "CHAPTER MIX",
+                "THE LAST PART"
+            ]
+        );
+        assert_eq!(book.lines[book.toc[2].1], "II.");
+    }
+
+    const STORY: &str = "The first paragraph opens the story.
+
+The second paragraph has the marked words in it.
+
+The third paragraph closes the story.
+";
+
+    fn book(content: &str) -> Book {
+        Book::new(content, "Test".to_string(), None)
+    }
+
+    fn offset_of(book: &Book, words: &str) -> usize {
+        let text = book.text.iter().collect::<String>();
+        text[..text.find(words).unwrap()].chars().count()
+    }
+
+    #[test]
+    fn migrates_line_numbers_to_anchors() {
+        let book = book(STORY);
+        let mut table =
+            toml::from_str::<toml::Table>("bookmarks = [2, 4]\nmarkers = [[2, 29, 41], [4, 4, 9]]")
+                .unwrap();
+        Config::migrate(&mut table, &book).unwrap();
+        let config = Config::deserialize(table).unwrap();
+        let offsets = config
+            .bookmarks
+            .iter()
+            .map(|bookmark| bookmark.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offsets,
+            [
+                offset_of(&book, "The second"),
+                offset_of(&book, "The third")
+            ]
+        );
+        assert_eq!(config.bookmarks[0], book.anchor(offsets[0]));
+        let marked = config
+            .markers
+            .iter()
+            .map(|marker| {
+                let start = marker.anchor.offset;
+                book.text[start..start + marker.length]
+                    .iter()
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(marked, ["marked words", "third"]);
+    }
+
+    #[test]
+    fn relocates_anchors_after_the_text_shifts() {
+        let old = book(STORY);
+        let new = book(&format!("A preface was added.\n\n{STORY}"));
+        let anchor = old.anchor(offset_of(&old, "marked words"));
+        let offset = new.relocate(&anchor).unwrap();
+        assert_eq!(offset, offset_of(&new, "marked words"));
+        // the same anchor still fits the old text
+        assert_eq!(old.relocate(&anchor), Some(anchor.offset));
+    }
+
+    #[test]
+    fn relocates_anchors_whose_context_was_edited() {
+        let old = book(STORY);
+        let edited = STORY.replace("has the marked", "holds the marked");
+        let new = book(&format!("Preface.\n\n{edited}"));
+        let anchor = old.anchor(offset_of(&old, "second paragraph"));
+        assert!(!new
+            .text
+            .iter()
+            .collect::<String>()
+            .contains(&anchor.context));
+        let offset = new.relocate(&anchor).unwrap();
+        assert_eq!(offset, offset_of(&new, "second paragraph"));
+    }
+
+    #[test]
+    fn keeps_anchors_whose_context_is_gone() {
+        let old = book(STORY);
+        let anchor = old.anchor(offset_of(&old, "marked words"));
+        let new = book("An entirely different book.\n");
+        assert_eq!(new.relocate(&anchor), None);
+        let mut config = Config {
+            bookmarks: vec![anchor.clone()],
+            ..Default::default()
+        };
+        config.relocate(&new);
+        assert_eq!(config.bookmarks, [anchor]);
+    }
+
+    /// A state showing `content` in lines of `text_width` columns.
+    fn reader(content: &str, text_width: usize) -> State {
+        let book = Book::new(content, "Test".to_string(), None);
+        let config = Config {
+            line_width: Some(text_width + GUTTER_WIDTH),
+            ..Default::default()
+        };
+        let mut state = State::new("test.txt", Settings::default(), config, book);
+        state.resize_screen(100, 20);
+        state
+    }
+
+    const WORDS: &str = "alpha beta gamma delta epsilon zeta eta theta";
+
+    #[test]
+    fn restores_saved_position_at_another_width() {
+        let content = (0..200)
+            .map(|i| format!("word{i}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let file =
+            std::env::temp_dir().join(format!("booklet-position-{}.toml", std::process::id()));
+        let mut state = reader(&content, 30);
+        state.config.file = Some(file.clone());
+        state.line_number = 10;
+        let first_word = state.book.lines[10].split(' ').next().unwrap().to_string();
+        state.save_position().unwrap();
+        assert!(state.config.last_read.is_some());
+        let saved = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        let config = Config::deserialize(toml::from_str::<toml::Table>(&saved).unwrap()).unwrap();
+        let mut other = reader(&content, 50);
+        other.config = config;
+        other.restore_position();
+        let line = &other.book.lines[other.line_number];
+        assert!(line.split(' ').any(|word| word == first_word), "{line}");
+        // without a position the reader starts at the first bookmark
+        let mut state = reader(&content, 30);
+        state.config.bookmarks = vec![state.book.anchor(state.book.offset_at(7, 0))];
+        state.restore_position();
+        assert_eq!(state.line_number, 7);
+    }
+
+    #[test]
+    fn saves_position_only_when_due_and_moved() {
+        let mut state = reader(WORDS, 20);
+        state.save_position().unwrap();
+        state.line_number = 1;
+        state.save_position_periodically().unwrap();
+        assert_eq!(state.config.position.as_ref().unwrap().offset, 0);
+        state.position_saved -= POSITION_INTERVAL;
+        state.save_position_periodically().unwrap();
+        let offset = state.book.offset_at(1, 0);
+        assert_eq!(state.config.position.as_ref().unwrap().offset, offset);
+        assert!(state.position_saved.elapsed() < POSITION_INTERVAL);
+    }
+
+    /// Answers every lookup with "not found" after a delay.
+    #[derive(Debug)]
+    struct Slow(Duration);
+
+    impl DictionaryProvider for Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn define<'a>(&'a self, _: &'a str) -> dictionary::Lookup<'a> {
+            Box::pin(async move {
+                task::sleep(self.0).await;
+                Ok(None)
+            })
+        }
+    }
+
+    /// A reader with the first word selected, looking it up in a dictionary taking `delay`.
+    fn lookup(delay: Duration) -> State {
+        let mut state = reader(WORDS, 20);
+        state.dictionary = Arc::new(Slow(delay));
+        state.select_word((0, 0));
+        task::block_on(state.define_selection());
+        state
+    }
+
+    /// Polls the pending lookup until it finishes.
+    fn finish_lookup(state: &mut State) {
+        for _ in 0..100 {
+            state.poll_lookup();
+            if state.lookup.is_none() {
+                return;
+            }
+            task::block_on(task::sleep(Duration::from_millis(10)));
+        }
+        panic!("lookup did not finish");
+    }
+
+    #[test]
+    fn pending_lookup_shows_progress_until_answered() {
+        let mut state = lookup(Duration::from_millis(50));
+        state.poll_lookup();
+        assert!(state.lookup.is_some());
+        assert_eq!(
+            state.message.as_deref(),
+            Some("(i) Looking up 'alpha'... 0s (Esc to cancel)")
+        );
+        finish_lookup(&mut state);
+        assert_eq!(
+            state.message.as_deref(),
+            Some("(i) No definition found for 'alpha'")
+        );
+    }
+
+    #[test]
+    fn slow_lookup_times_out() {
+        let mut state = reader(WORDS, 20);
+        state.lookup_timeout = Duration::from_millis(20);
+        state.dictionary = Arc::new(Slow(Duration::from_secs(60)));
+        state.select_word((0, 0));
+        task::block_on(state.define_selection());
+        finish_lookup(&mut state);
+        assert_eq!(
+            state.message.as_deref(),
+            Some("(!) Lookup failed: timed out after 0s")
+        );
+    }
+
+    #[test]
+    fn cancelled_lookup_reports_nothing() {
+        let mut state = lookup(Duration::from_secs(60));
+        task::block_on(state.cancel_lookup());
+        assert!(state.lookup.is_none());
+        assert_eq!(state.message.as_deref(), Some("(i) Lookup cancelled"));
+        state.poll_lookup();
+        assert_eq!(state.message.as_deref(), Some("(i) Lookup cancelled"));
+        // a new lookup replaces one still pending
+        let mut state = lookup(Duration::from_secs(60));
+        state.dictionary = Arc::new(Slow(Duration::ZERO));
+        task::block_on(state.define_selection());
+        finish_lookup(&mut state);
+        assert_eq!(
+            state.message.as_deref(),
+            Some("(i) No definition found for 'alpha'")
+        );
+    }
+
+    #[test]
+    fn word_motions_cross_wrapped_lines() {
+        let mut state = reader(WORDS, 20);
+        assert_eq!(
+            state.book.lines[..3],
+            ["alpha beta gamma", "delta epsilon zeta", "eta theta"]
+        );
+        state.move_cursor(Motion::WordForward);
+        assert_eq!(state.cursor, Some((0, 0)));
+        state.move_cursor(Motion::WordForward);
+        state.move_cursor(Motion::WordForward);
+        assert_eq!(state.cursor, Some((0, 11)));
+        state.move_cursor(Motion::WordForward);
+        assert_eq!(state.cursor, Some((1, 0)));
+        assert_eq!(state.line_number, 1);
+        state.move_cursor(Motion::WordBackward);
+        assert_eq!(state.cursor, Some((0, 11)));
+        state.move_cursor(Motion::WordEnd);
+        assert_eq!(state.cursor, Some((0, 15)));
+        state.move_cursor(Motion::WordEnd);
+        assert_eq!(state.cursor, Some((1, 4)));
+        assert_eq!(state.selection, Some(Selection::line(1, 0, 5)));
+        assert_eq!(
+            state.get_text(state.selection.unwrap()).as_deref(),
+            Some("delta")
+        );
+        state.move_cursor(Motion::Left);
+        assert_eq!(state.cursor, Some((1, 3)));
+    }
+
+    #[test]
+    fn visual_mode_selects_from_start_to_cursor() {
+        let mut state = reader(WORDS, 20);
+        state.move_cursor(Motion::WordForward);
+        state.move_cursor(Motion::WordForward);
+        state.toggle_visual();
+        assert_eq!(state.visual, Some((0, 6)));
+        for _ in 0..3 {
+            state.move_cursor(Motion::WordForward);
+        }
+        assert_eq!(state.selection, Some(Selection::new((0, 6), (1, 13))));
+        let text = state.get_text(state.selection.unwrap());
+        assert_eq!(text.as_deref(), Some("beta gamma delta epsilon"));
+        // moving before the start selects backwards
+        for _ in 0..4 {
+            state.move_cursor(Motion::WordBackward);
+        }
+        assert_eq!(state.selection, Some(Selection::new((0, 0), (0, 10))));
+        state.toggle_visual();
+        assert_eq!(state.visual, None);
+        assert_eq!(state.selection, Some(Selection::new((0, 0), (0, 10))));
+    }
+
+    #[test]
+    fn dragging_selects_whole_words_across_lines() {
+        let mut state = reader(WORDS, 20);
+        state.start_drag((0, 8));
+        state.update_drag((1, 2), false);
+        assert_eq!(state.selection, Some(Selection::new((0, 6), (1, 5))));
+        // dragging upwards keeps the start
+        state.update_drag((0, 1), true);
+        assert_eq!(state.selection, Some(Selection::new((0, 0), (0, 10))));
+        assert_eq!(state.drag, None);
+        // a click without dragging selects the word under the mouse
+        state.start_drag((2, 5));
+        state.update_drag((2, 5), true);
+        assert_eq!(state.selection, Some(Selection::line(2, 4, 9)));
+    }
+
+    #[test]
+    fn shift_click_extends_the_selection() {
+        let mut state = reader(WORDS, 20);
+        state.extend_selection((0, 12));
+        assert_eq!(state.selection, Some(Selection::line(0, 11, 16)));
+        state.extend_selection((2, 1));
+        assert_eq!(state.selection, Some(Selection::new((0, 11), (2, 3))));
+        let text = state.get_text(state.selection.unwrap());
+        assert_eq!(text.as_deref(), Some("gamma delta epsilon zeta eta"));
+        assert_eq!(
+            Selection::new((0, 11), (2, 3)).range_on(1, 18),
+            Some((0, 18))
+        );
+    }
+
+    #[test]
+    fn yanks_selection_focus_line_or_cited_highlight() {
+        let mut state = reader(&format!("CHAPTER I\n\n{WORDS}"), 20);
+        state.book.author = Some("Anon".to_string());
+        state.line_number = 2;
+        assert_eq!(state.yank_text().as_deref(), Some("alpha beta gamma"));
+        state.extend_selection((2, 12));
+        state.extend_selection((4, 1));
+        assert_eq!(
+            state.yank_text().as_deref(),
+            Some("gamma delta epsilon zeta eta")
+        );
+        state.toggle_marker().unwrap();
+        state.open_markers();
+        assert_eq!(
+            state.yank_text().as_deref(),
+            Some("\"gamma delta epsilon zeta eta\"\n— Anon, Test, CHAPTER I")
+        );
+        state.close_overlay();
+        state.clear_selection();
+        state.line_number = 1;
+        assert_eq!(state.yank_text(), None);
+    }
+
+    #[test]
+    fn cursor_moves_over_whole_grapheme_clusters() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        let mut state = reader(&format!("cafe\u{301} nai\u{308}ve {family} end"), 40);
+        state.move_cursor(Motion::WordEnd);
+        assert_eq!(state.cursor, Some((0, 0)));
+        // the accent combines with the e before it
+        state.move_cursor(Motion::WordEnd);
+        assert_eq!(state.cursor, Some((0, 3)));
+        state.move_cursor(Motion::Right);
+        assert_eq!(state.cursor, Some((0, 5)));
+        state.move_cursor(Motion::WordEnd);
+        assert_eq!(state.cursor, Some((0, 11)));
+        assert_eq!(
+            state.get_text(state.selection.unwrap()).as_deref(),
+            Some("nai\u{308}ve")
+        );
+        state.move_cursor(Motion::Right);
+        state.move_cursor(Motion::Right);
+        assert_eq!(state.cursor, Some((0, 13)));
+        state.move_cursor(Motion::Right);
+        assert_eq!(state.cursor, Some((0, 18)));
+        state.move_cursor(Motion::Left);
+        assert_eq!(state.cursor, Some((0, 13)));
+        assert_eq!(state.selection, Some(Selection::line(0, 13, 18)));
+        assert_eq!(
+            state.get_text(state.selection.unwrap()).as_deref(),
+            Some(family)
+        );
+        // the emoji is two columns wide and both of them hit it
+        assert_eq!(state.book.column_at_index(0, 13), 11);
+        assert_eq!(state.book.index_at_column(0, 12), Some(13));
+        assert_eq!(state.book.column_at_index(0, 18), 13);
+    }
+
+    #[test]
+    fn keeps_state_in_data_dir_or_next_to_book_without_home() {
+        let dir = env::temp_dir().join(format!("booklet-data-{}", std::process::id()));
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a/novel.txt"), "").unwrap();
+        let path = dir.join("a/novel.txt").to_string_lossy().to_string();
+        let same = dir.join("a/../a/./novel.txt").to_string_lossy().to_string();
+        let other = dir.join("b/novel.txt").to_string_lossy().to_string();
+        let (home, data_home) = (env::var_os("HOME"), env::var_os("XDG_DATA_HOME"));
+        env::set_var("XDG_DATA_HOME", dir.join("data"));
+        let paths = [&path, &same, &other].map(|path| Config::data_path(path));
+        env::remove_var("XDG_DATA_HOME");
+        env::remove_var("HOME");
+        let without_home = Config::data_path(&path);
+        let config = Config::from_path(&path, &book("Text."), false).unwrap();
+        if let Some(home) = home {
+            env::set_var("HOME", home);
+        }
+        if let Some(data_home) = data_home {
+            env::set_var("XDG_DATA_HOME", data_home);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        let [first, same, other] = paths.map(Option::unwrap);
+        assert_eq!(
+            first.parent(),
+            Some(dir.join("data/booklet/books").as_path())
+        );
+        let name = first.file_name().unwrap().to_string_lossy().to_string();
+        let hash = name
+            .strip_prefix("novel.txt-")
+            .and_then(|name| name.strip_suffix(".toml"))
+            .unwrap();
+        assert!(hash.len() == 16 && hash.chars().all(|char| char.is_ascii_hexdigit()));
+        // the hash is taken from the canonical path, so only different books get other files
+        assert_eq!(first, same);
+        assert_ne!(first, other);
+        assert_eq!(without_home, None);
+        assert_eq!(config.file, Config::sidecar_path(&path));
+    }
+
+    /// Presses the keys of a sequence like `25j` and returns the action they complete together
+    /// with its count.
+    fn press(state: &mut State, keys: &str) -> Option<(Action, Option<usize>)> {
+        let mut resolved = None;
+        for key in KeyPress::parse_sequence(keys).unwrap() {
+            resolved = state.press_key(key);
+        }
+        resolved
+    }
+
+    #[test]
+    fn count_repeats_movement() {
+        let mut state = state();
+        assert_eq!(press(&mut state, "25j"), Some((Action::MoveDown, Some(25))));
+        assert!(state.navigate(Action::MoveDown, Some(25)));
+        assert_eq!(state.line_number, 25);
+        assert_eq!(state.count, None);
+    }
+
+    #[test]
+    fn count_repeats_bookmark_jumps() {
+        let mut state = state();
+        for line_number in [10, 20, 30, 40] {
+            state.add_bookmark(line_number).unwrap();
+        }
+        assert_eq!(
+            press(&mut state, "3gn"),
+            Some((Action::NextBookmark, Some(3)))
+        );
+        state.navigate(Action::NextBookmark, Some(3));
+        assert_eq!(state.line_number, 30);
+    }
+
+    #[test]
+    fn count_goes_to_line() {
+        let mut state = state();
+        assert_eq!(press(&mut state, "5gg"), Some((Action::GotoTop, Some(5))));
+        state.navigate(Action::GotoTop, Some(5));
+        assert_eq!(state.line_number, 4);
+        state.navigate(Action::GotoBottom, Some(1));
+        assert_eq!(state.line_number, 0);
+    }
+
+    #[test]
+    fn leading_zero_is_not_a_count() {
+        let mut state = state();
+        assert_eq!(press(&mut state, "0"), None);
+        assert_eq!(state.count, None);
+        assert_eq!(press(&mut state, "10j"), Some((Action::MoveDown, Some(10))));
+    }
+
+    #[test]
+    fn esc_clears_count() {
+        let mut state = state();
+        assert_eq!(press(&mut state, "7"), None);
+        assert_eq!(state.count, Some(7));
+        assert_eq!(press(&mut state, "<Esc>"), None);
+        assert_eq!(state.count, None);
+        assert_eq!(press(&mut state, "j"), Some((Action::MoveDown, None)));
+    }
+
+    #[test]
+    fn overlay_keys_go_through_keymap() {
+        let mut state = state();
+        assert_eq!(press(&mut state, "x"), Some((Action::ToggleBookmark, None)));
+        state.open_contents();
+        assert_eq!(press(&mut state, "x"), Some((Action::RemoveEntry, None)));
+        assert_eq!(press(&mut state, "<Enter>"), Some((Action::Select, None)));
+    }
+
+    #[test]
+    fn paging_stops_at_first_and_last_line() {
+        let mut state = state();
+        let last = state.book.line_count - 1;
+        state.page_up(1);
+        assert_eq!(state.line_number, 0);
+        state.page_down(1);
+        assert_eq!(state.line_number, state.page_height());
+        state.page_down(1000);
+        assert_eq!(state.line_number, last);
+        state.page_up(1000);
+        assert_eq!(state.line_number, 0);
+    }
+
+    #[test]
+    fn half_paging_stops_at_first_and_last_line() {
+        let mut state = state();
+        let last = state.book.line_count - 1;
+        state.half_page_down(1);
+        assert_eq!(state.line_number, 10);
+        state.half_page_up(2);
+        assert_eq!(state.line_number, 0);
+        state.half_page_down(1000);
+        assert_eq!(state.line_number, last);
+    }
+
+    #[test]
+    fn wheel_scrolls_text_or_overlay() {
+        let mut state = state();
+        state.wheel_down();
+        assert_eq!(state.line_number, WHEEL_LINES);
+        state.wheel_up();
+        assert_eq!(state.line_number, 0);
+        state.open_contents();
+        assert_eq!(state.overlay, Some(Overlay::Contents(0)));
+        state.wheel_down();
+        assert_eq!(state.overlay, Some(Overlay::Contents(2)));
+        assert_eq!(state.line_number, 0);
+        state.wheel_up();
+        assert_eq!(state.overlay, Some(Overlay::Contents(0)));
+    }
 }
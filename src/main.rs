@@ -1,23 +1,43 @@
 use std::env;
+use std::fs;
 use std::io::Stdout;
 use std::io::Write;
+use std::time::SystemTime;
 
+use booklet::cache::DefinitionCache;
+use booklet::clipboard;
+use booklet::export::Export;
+use booklet::keymap::Action;
+use booklet::keymap::Key;
+use booklet::keymap::KeyPress;
+use booklet::settings::Settings;
+use booklet::theme::Color;
+use booklet::theme::Theme;
+use booklet::vocabulary::Vocabulary;
 use booklet::Book;
 use booklet::Config;
+use booklet::Definition;
 
-use terminal::Action;
+use terminal::Action as TermAction;
 use terminal::Clear;
 use terminal::Event;
 use terminal::KeyCode;
 use terminal::KeyEvent;
+use terminal::KeyModifiers;
 use terminal::MouseButton;
 use terminal::MouseEvent;
 use terminal::Retrieved;
 use terminal::Terminal;
 use terminal::Value;
 
+use booklet::wrap_text;
 use booklet::Codes;
+use booklet::Overlay;
 use booklet::State;
+use booklet::DEFAULT_LINE_WIDTH;
+use booklet::GUTTER_WIDTH;
+use booklet::LOOKUP_POLL_INTERVAL;
+use booklet::POSITION_INTERVAL;
 
 const OFFSET: usize = 15;
 
@@ -29,260 +49,582 @@ async fn main() {
 }
 
 async fn run() -> anyhow::Result<()> {
-    let mut args = env::args().skip(1);
-    let path = match args.next() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|arg| arg == "export") {
+        return export(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "vocabulary") {
+        return export_vocabulary(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "review") {
+        return review();
+    }
+    if args.first().is_some_and(|arg| arg == "clear-cache") {
+        let count = DefinitionCache::clear()?;
+        println!("Removed {count} cached definitions");
+        return Ok(());
+    }
+    let mut path = None;
+    let mut start_at_top = false;
+    for arg in args {
+        match arg.as_str() {
+            "--top" => start_at_top = true,
+            _ => path = Some(arg),
+        }
+    }
+    let path = match path {
         Some(path) => path,
         None => return Ok(()),
     };
-    let mut term = terminal::stdout();
-    term.batch(Action::EnterAlternateScreen)?;
-    term.batch(Action::EnableRawMode)?;
-    term.batch(Action::HideCursor)?;
-    term.batch(Action::EnableMouseCapture)?;
-    term.flush_batch()?;
-    let config = Config::from_path(&path)?;
     let book = Book::from_path(&path)?;
-    let mut state = State::new(&path, config, book);
-    if let Some((cols, rows)) = read_size(&mut term)? {
+    let settings = Settings::load()?;
+    let config = Config::from_path(&path, &book, settings.sidecar)?;
+    let mut state = State::new(&path, settings, config, book);
+    if let Some(warning) = state.book.warnings.first() {
+        let skipped = state.book.warnings.len();
+        state.show_message(&format!(
+            "(!) Skipped {skipped} part(s) of the book: {warning}"
+        ));
+    }
+    let mut term = terminal::stdout();
+    enter_terminal(&mut term)?;
+    let result = read(&mut term, &mut state, start_at_top).await;
+    // restore the terminal even if reading failed, so that the error can be seen
+    let left = leave_terminal(&mut term);
+    result.and(left)
+}
+
+/// Shows the book and handles input until the reader quits.
+async fn read(
+    term: &mut Terminal<Stdout>,
+    state: &mut State,
+    start_at_top: bool,
+) -> anyhow::Result<()> {
+    if let Some((cols, rows)) = read_size(term)? {
         state.resize_screen(cols as usize, rows as usize);
     }
-    state.goto_next_bookmark();
+    if !start_at_top {
+        state.restore_position();
+    }
     loop {
         if state.update_screen {
-            render(&mut term, &state)?;
+            render(term, state)?;
             state.update_screen = false;
         }
-        if let Retrieved::Event(Some(event)) = term.get(Value::Event(None))? {
+        // wake up to save the position, and while a lookup is pending so that its result shows
+        // up without input
+        let mut timeout = POSITION_INTERVAL.saturating_sub(state.position_saved.elapsed());
+        if state.lookup.is_some() {
+            timeout = timeout.min(LOOKUP_POLL_INTERVAL);
+        }
+        if let Retrieved::Event(Some(event)) = term.get(Value::Event(Some(timeout)))? {
             match event {
                 Event::Resize => {
-                    if let Some((cols, rows)) = read_size(&mut term)? {
+                    if let Some((cols, rows)) = read_size(term)? {
                         state.resize_screen(cols as usize, rows as usize);
                     }
                 }
-                Event::Key(key) => {
-                    match key.code {
-                        KeyCode::Char(char) => {
-                            match char {
-                                'q' => break,
-                                'j' => state.move_down(),
-                                'k' => state.move_up(),
-                                'g' => {
-                                    if let Some(key) = read_key(&mut term)? {
-                                        match key.code {
-                                            KeyCode::Esc => break,
-                                            KeyCode::Char(char) => match char {
-                                                'g' => state.goto_top(),
-                                                'e' => state.goto_bottom(),
-                                                'n' => state.goto_next_bookmark(),
-                                                'p' => state.goto_prev_bookmark(),
-                                                _ => (),
-                                            },
-                                            _ => (),
-                                        }
-                                    }
-                                }
-                                'x' => state.toggle_bookmark(state.line_number)?,
-                                'd' => state.define_selection().await?,
-                                'f' => state.toggle_focus_mode()?,
-                                // 'm' => {
-                                //     if let Some(selection) = state.selection {
-                                //         match state
-                                //             .config
-                                //             .markers
-                                //             .iter()
-                                //             .position(|item| item == &selection)
-                                //         {
-                                //             Some(index) => {
-                                //                 state.config.markers.remove(index);
-                                //             }
-                                //             None => {
-                                //                 state.config.markers.push(selection);
-                                //             }
-                                //         }
-                                //         state.config.write(&path)?;
-                                //         render(&mut term, &state)?;
-                                //     }
-                                // }
-                                _ => (),
-                            }
+                Event::Key(key) if state.overlay.is_some() => {
+                    let action = key_press(key)
+                        .and_then(|key| state.press_key(key))
+                        .map(|(action, _)| action);
+                    match (state.overlay, action) {
+                        (_, Some(Action::MoveDown)) => state.overlay_down(),
+                        (_, Some(Action::MoveUp)) => state.overlay_up(),
+                        (_, Some(Action::Select)) => state.select_overlay(),
+                        (
+                            Some(Overlay::Markers(_) | Overlay::Note(_)),
+                            Some(Action::RemoveEntry),
+                        ) => state.remove_overlay_marker()?,
+                        (Some(Overlay::Vocabulary(_)), Some(Action::RemoveEntry)) => {
+                            state.remove_vocabulary_entry()?
+                        }
+                        (Some(Overlay::Markers(_)), Some(Action::EditNote)) => {
+                            state.open_overlay_note()
                         }
-                        KeyCode::Esc => {
-                            state.clear_selection();
-                            state.clear_definition();
-                            state.clear_message();
-                            state.message = None;
+                        (Some(Overlay::Note(_)), Some(Action::EditEntry)) => {
+                            edit_note(term, state)?
                         }
+                        (Some(Overlay::Markers(_) | Overlay::Note(_)), Some(Action::Yank)) => {
+                            yank(term, state)?
+                        }
+                        (
+                            _,
+                            Some(
+                                Action::Quit
+                                | Action::Cancel
+                                | Action::OpenContents
+                                | Action::OpenMarkers
+                                | Action::OpenVocabulary,
+                            ),
+                        ) => state.close_overlay(),
                         _ => (),
                     }
                 }
-                Event::Mouse(MouseEvent::Up(MouseButton::Left, col, row, _)) => {
-                    let col = col as usize;
-                    let row = row as usize;
-                    let pos = (state.line_number + row).saturating_sub(OFFSET);
-                    if col >= state.pad_left + 8 {
-                        let col = col.saturating_sub(state.pad_left).saturating_sub(10);
-                        if let Some(line) = state.book.lines.get(pos) {
-                            let line = line.replace("\x1b[4m", "");
-                            let chars = line.chars().collect::<Vec<_>>();
-                            if let Some(char) = chars.get(col) {
-                                // mark words
-                                if char.is_alphabetic() {
-                                    let mut start = col;
-                                    for i in (0..col).rev() {
-                                        if let Some(char) = chars.get(i) {
-                                            if !char.is_alphabetic() {
-                                                start = i + 1;
-                                                break;
-                                            }
-                                        }
-                                        start = 0;
-                                    }
-                                    let mut end = col;
-                                    for i in col..chars.len() {
-                                        if let Some(char) = chars.get(i) {
-                                            if !char.is_alphabetic() {
-                                                end = i;
-                                                break;
-                                            }
-                                        }
-                                        end = chars.len();
-                                    }
-                                    state.set_selection((pos, start, end));
-                                }
-                                // mark numbers
-                                if char.is_numeric() {
-                                    let mut start = col;
-                                    for i in (0..col).rev() {
-                                        if let Some(char) = chars.get(i) {
-                                            if !char.is_numeric() {
-                                                start = i + 1;
-                                                break;
-                                            }
-                                        }
-                                        start = 0;
-                                    }
-                                    let mut end = col;
-                                    for i in col..chars.len() {
-                                        if let Some(char) = chars.get(i) {
-                                            if !char.is_numeric() {
-                                                end = i;
-                                                break;
-                                            }
-                                        }
-                                        end = chars.len();
-                                    }
-                                    state.set_selection((pos, start, end));
-                                }
-                            }
+                Event::Key(key) => {
+                    if let Some((action, count)) =
+                        key_press(key).and_then(|key| state.press_key(key))
+                    {
+                        if !perform(term, state, action, count).await? {
+                            break;
                         }
                     }
                 }
+                Event::Mouse(MouseEvent::Down(MouseButton::Left, col, row, modifiers)) => {
+                    if let Some(position) = screen_position(state, col, row) {
+                        if modifiers.contains(KeyModifiers::SHIFT) {
+                            state.clear_cursor();
+                            state.extend_selection(position);
+                        } else {
+                            state.start_drag(position);
+                        }
+                    }
+                }
+                Event::Mouse(MouseEvent::Drag(MouseButton::Left, col, row, _)) => {
+                    if let Some(position) = screen_position(state, col, row) {
+                        state.update_drag(position, false);
+                    }
+                }
+                Event::Mouse(MouseEvent::ScrollDown(..)) => state.wheel_down(),
+                Event::Mouse(MouseEvent::ScrollUp(..)) => state.wheel_up(),
+                Event::Mouse(MouseEvent::Up(MouseButton::Left, col, row, _)) => {
+                    match screen_position(state, col, row) {
+                        Some(position) => state.update_drag(position, true),
+                        None => state.drag = None,
+                    }
+                }
                 _ => (),
             }
         }
+        state.sync_cursor();
+        state.poll_lookup();
+        state.save_position_periodically()?;
+    }
+    state.save_position()
+}
+
+/// Runs an action bound to a key, repeating movements `count` times. Returns `false` if the
+/// reader should quit.
+async fn perform(
+    term: &mut Terminal<Stdout>,
+    state: &mut State,
+    action: Action,
+    count: Option<usize>,
+) -> anyhow::Result<bool> {
+    match action {
+        Action::Quit => return Ok(false),
+        Action::Cancel if state.lookup.is_some() => state.cancel_lookup().await,
+        Action::Cancel => {
+            state.clear_cursor();
+            state.clear_selection();
+            state.clear_definition();
+            state.clear_search();
+            state.clear_message();
+            state.message = None;
+        }
+        Action::ToggleBookmark => state.toggle_bookmark(state.line_number)?,
+        Action::Define => state.define_selection().await,
+        Action::ToggleFocusMode => state.toggle_focus_mode()?,
+        Action::CycleTheme => state.cycle_theme()?,
+        Action::SearchForward | Action::SearchBackward => {
+            let backward = action == Action::SearchBackward;
+            state.start_search(backward);
+            let prompt = if backward { "?" } else { "/" };
+            let query = read_input(term, state, prompt, "", |state, query| {
+                state.update_search(query)
+            })?;
+            match query {
+                Some(_) => state.confirm_search(),
+                None => state.cancel_search(),
+            }
+        }
+        Action::OpenContents => state.open_contents(),
+        Action::OpenMarkers => state.open_markers(),
+        Action::OpenVocabulary => state.open_vocabulary()?,
+        Action::IncreaseLineWidth => state.increase_line_width()?,
+        Action::DecreaseLineWidth => state.decrease_line_width()?,
+        Action::ToggleMarker => state.toggle_marker()?,
+        Action::CycleMarkerColor => state.cycle_marker_color()?,
+        Action::EditNote => {
+            state.open_note()?;
+            if state
+                .overlay_marker()
+                .is_some_and(|marker| marker.note.is_none())
+            {
+                edit_note(term, state)?;
+            }
+        }
+        Action::ToggleVisual => state.toggle_visual(),
+        Action::Yank => yank(term, state)?,
+        // only bound in overlays
+        Action::RemoveEntry | Action::EditEntry | Action::Select => (),
+        Action::MoveDown
+        | Action::MoveUp
+        | Action::PageDown
+        | Action::PageUp
+        | Action::HalfPageDown
+        | Action::HalfPageUp
+        | Action::GotoTop
+        | Action::GotoBottom
+        | Action::NextBookmark
+        | Action::PrevBookmark
+        | Action::NextChapter
+        | Action::PrevChapter
+        | Action::NextMatch
+        | Action::PrevMatch
+        | Action::CursorLeft
+        | Action::CursorRight
+        | Action::WordForward
+        | Action::WordBackward
+        | Action::WordEnd => {
+            state.navigate(action, count);
+        }
+    }
+    Ok(true)
+}
+
+/// Converts a key event of the terminal into a key of the keymap.
+fn key_press(event: KeyEvent) -> Option<KeyPress> {
+    let key = match event.code {
+        KeyCode::Char(char) => Key::Char(char),
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Tab | KeyCode::BackTab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::F(number) => Key::F(number),
+        KeyCode::Null => return None,
+    };
+    let press = KeyPress {
+        key,
+        ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        alt: event.modifiers.contains(KeyModifiers::ALT),
+        shift: event.modifiers.contains(KeyModifiers::SHIFT) || event.code == KeyCode::BackTab,
+    };
+    Some(press.normalize())
+}
+
+/// Copies text to the clipboard through the terminal, and through the configured command for
+/// terminals without OSC 52 support.
+fn yank(term: &mut Terminal<Stdout>, state: &mut State) -> anyhow::Result<()> {
+    let text = match state.yank_text() {
+        Some(text) => text,
+        None => {
+            state.show_message("(i) Nothing to copy");
+            return Ok(());
+        }
+    };
+    term.write_all(clipboard::osc52(&text).as_bytes())?;
+    term.flush()?;
+    if let Some(command) = state.settings.clipboard.clone() {
+        if let Err(err) = clipboard::pipe(&command, &text) {
+            state.show_message(&format!("(!) Copy failed: {err}"));
+            return Ok(());
+        }
     }
-    term.batch(Action::DisableMouseCapture)?;
-    term.batch(Action::ShowCursor)?;
-    term.batch(Action::DisableRawMode)?;
-    term.batch(Action::LeaveAlternateScreen)?;
+    state.show_message(&format!("(i) Copied {} chars", text.chars().count()));
+    Ok(())
+}
+
+/// Maps a cell of the screen to a position in the book as `(line, char index)`.
+fn screen_position(state: &State, col: u16, row: u16) -> Option<(usize, usize)> {
+    let (col, row) = (col as usize, row as usize);
+    let line_number = (state.line_number + row).checked_sub(OFFSET)?;
+    let column = col.checked_sub(state.pad_left + GUTTER_WIDTH)?;
+    let index = state.book.index_at_column(line_number, column)?;
+    Some((line_number, index))
+}
+
+fn enter_terminal(term: &mut Terminal<Stdout>) -> anyhow::Result<()> {
+    term.batch(TermAction::EnterAlternateScreen)?;
+    term.batch(TermAction::EnableRawMode)?;
+    term.batch(TermAction::HideCursor)?;
+    term.batch(TermAction::EnableMouseCapture)?;
+    term.flush_batch()?;
+    Ok(())
+}
+
+fn leave_terminal(term: &mut Terminal<Stdout>) -> anyhow::Result<()> {
+    term.batch(TermAction::DisableMouseCapture)?;
+    term.batch(TermAction::ShowCursor)?;
+    term.batch(TermAction::DisableRawMode)?;
+    term.batch(TermAction::LeaveAlternateScreen)?;
     term.flush_batch()?;
     Ok(())
 }
 
+/// Writes the bookmarks and highlights of a book as Markdown or JSON.
+fn export(args: &[String]) -> anyhow::Result<()> {
+    let mut path = None;
+    let mut output = None;
+    let mut json = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--markdown" => json = false,
+            "-o" | "--output" => output = args.next(),
+            _ => path = Some(arg),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => anyhow::bail!("Usage: booklet export <file> [--json] [--output <file>]"),
+    };
+    let mut book = Book::from_path(path)?;
+    for warning in &book.warnings {
+        eprintln!("(!) Skipped part of the book: {warning}");
+    }
+    let settings = Settings::load()?;
+    let config = Config::from_path(path, &book, settings.sidecar)?;
+    if let Some(line_width) = config.line_width.or(settings.line_width) {
+        book.reflow(line_width);
+    }
+    let export = Export::new(&book, &config)?;
+    let content = match json {
+        true => export.to_json()?,
+        false => export.to_markdown(),
+    };
+    match output {
+        Some(output) => fs::write(output, content)?,
+        None => print!("{content}"),
+    }
+    Ok(())
+}
+
+fn export_vocabulary(args: &[String]) -> anyhow::Result<()> {
+    let mut output = None;
+    let mut csv = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--csv" => csv = true,
+            "--tsv" => csv = false,
+            "-o" | "--output" => output = args.next(),
+            _ => anyhow::bail!("Usage: booklet vocabulary [--csv | --tsv] [--output <file>]"),
+        }
+    }
+    let content = Vocabulary::load()?.to_anki(csv);
+    match output {
+        Some(output) => fs::write(output, content)?,
+        None => print!("{content}"),
+    }
+    Ok(())
+}
+
+/// Reviews the words of the vocabulary that are due, showing each word with the sentence it was
+/// found in and revealing the definition before asking for a grade.
+fn review() -> anyhow::Result<()> {
+    let theme = Settings::load()?.theme_or_default(None);
+    let mut vocabulary = Vocabulary::load()?;
+    let due = vocabulary.due(now());
+    if due.is_empty() {
+        println!("No words due for review");
+        return Ok(());
+    }
+    let mut term = terminal::stdout();
+    enter_terminal(&mut term)?;
+    let result = review_words(&mut term, &theme, &mut vocabulary, &due);
+    leave_terminal(&mut term)?;
+    let reviewed = result?;
+    println!("Reviewed {reviewed} of {} words", due.len());
+    Ok(())
+}
+
+/// Runs the review loop, returning the number of words graded.
+fn review_words(
+    term: &mut Terminal<Stdout>,
+    theme: &Theme,
+    vocabulary: &mut Vocabulary,
+    due: &[usize],
+) -> anyhow::Result<usize> {
+    for (reviewed, &index) in due.iter().enumerate() {
+        let mut revealed = false;
+        loop {
+            render_review(
+                term,
+                theme,
+                vocabulary,
+                index,
+                revealed,
+                (reviewed, due.len()),
+            )?;
+            let key = match term.get(Value::Event(None))? {
+                Retrieved::Event(Some(Event::Key(key))) => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(reviewed),
+                KeyCode::Enter | KeyCode::Char(' ') => revealed = true,
+                KeyCode::Char(char @ '0'..='5') if revealed => {
+                    let quality = char.to_digit(10).unwrap_or_default() as u8;
+                    vocabulary.entries[index].review.grade(quality, now());
+                    vocabulary.save()?;
+                    break;
+                }
+                _ => (),
+            }
+        }
+    }
+    Ok(due.len())
+}
+
+fn render_review(
+    term: &mut Terminal<Stdout>,
+    theme: &Theme,
+    vocabulary: &Vocabulary,
+    index: usize,
+    revealed: bool,
+    (reviewed, total): (usize, usize),
+) -> anyhow::Result<()> {
+    let entry = &vocabulary.entries[index];
+    let (cols, rows) = read_size(term)?.unwrap_or((80, 24));
+    let (cols, rows) = (cols as usize, rows as usize);
+    let width = cols.min(DEFAULT_LINE_WIDTH).saturating_sub(GUTTER_WIDTH);
+    let pad_left = (cols.saturating_sub(width)) / 2;
+    let dimmed = theme.fg(theme.dimmed);
+    let mut lines = vec![
+        format!("{dimmed}{}/{total}", reviewed + 1),
+        String::new(),
+        format!("\x1b[1m{}{}", theme.fg(theme.text), entry.word),
+        String::new(),
+    ];
+    for line in wrap_text(&entry.sentence, width) {
+        lines.push(format!("\x1b[3m{}{line}", theme.fg(theme.faded)));
+    }
+    lines.push(format!("{dimmed}{}, line {}", entry.book, entry.line));
+    lines.push(String::new());
+    if revealed {
+        lines.extend(definition_lines(&entry.definition, width, theme));
+        lines.push(String::new());
+        lines.push(format!(
+            "{dimmed}grade 0-5: 0 forgotten, 3 recalled with effort, 5 perfect"
+        ));
+    } else {
+        lines.push(format!("{dimmed}space show definition, q quit"));
+    }
+    let top = rows.saturating_sub(lines.len()) / 3;
+    let reset = theme.reset();
+    for i in 0..rows {
+        clear_line(term, theme, i)?;
+        if let Some(line) = i.checked_sub(top).and_then(|index| lines.get(index)) {
+            term.write_all(format!("{: >pad_left$}{line}{reset}", "").as_bytes())?;
+        }
+    }
+    term.flush()?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
 fn render(term: &mut Terminal<Stdout>, state: &State) -> anyhow::Result<()> {
+    if let Some(overlay) = state.overlay {
+        return render_overlay(term, state, overlay);
+    }
+    let theme = &state.theme;
+    let reset = theme.reset();
+    let definition = state.definition.as_ref().map(|(selection, definition)| {
+        let row = selection.end.0;
+        let width = state.book.line_width.saturating_sub(GUTTER_WIDTH);
+        (row, definition_lines(definition, width, theme))
+    });
     for i in 0..state.screen_height {
-        term.act(Action::MoveCursorTo(0, i as u16))?;
-        term.batch(Action::ClearTerminal(Clear::CurrentLine))?;
+        clear_line(term, theme, i)?;
         if state.line_number + i >= OFFSET {
             let pos = (state.line_number + i).saturating_sub(OFFSET);
             if let Some(line) = state.book.lines.get(pos) {
                 let mut line = line.to_string();
                 let line_number = pos;
-                let is_bookmarked = state.config.bookmarks.contains(&line_number);
+                let is_bookmarked = state.has_bookmark(line_number);
                 // determine line color
-                let mut line_color = if state.config.focus_mode.unwrap_or_default() {
+                let mut line_color = if state.focus_mode() {
                     match i {
-                        i if i + 1 == OFFSET => "\x1b[38;2;160;160;160m",
-                        i if i == OFFSET => "\x1b[38;2;240;240;240m",
-                        i if i == OFFSET + 1 => "\x1b[38;2;160;160;160m",
-                        _ => "\x1b[38;2;100;100;100m",
+                        i if i + 1 == OFFSET => theme.fg(theme.faded),
+                        i if i == OFFSET => theme.emphasis(theme.text),
+                        i if i == OFFSET + 1 => theme.fg(theme.faded),
+                        _ => theme.fg(theme.dimmed),
                     }
                 } else {
-                    "\x1b[38;2;240;240;240m"
+                    theme.fg(theme.text)
                 };
-                // insert selections
-                if let Some(selection) = &state.selection {
-                    let (row, start, end) = selection;
-                    if row == &pos {
-                        let mut chars = Vec::new();
-                        for (i, char) in line.chars().enumerate() {
-                            if &i == start {
-                                chars.push(Codes::BACKGROUND_SELECTION);
-                            }
-                            if &i == end {
-                                chars.push(Codes::RESET_BACKGROUND);
-                            }
-                            chars.push(char);
+                // insert markers and selections
+                let mut ranges = Vec::new();
+                if let Some(search) = &state.search {
+                    for (row, start, end) in &search.matches {
+                        if row == &pos {
+                            ranges.push((*start, *end, Codes::BACKGROUND_SEARCH));
                         }
-                        line = chars.iter().collect();
                     }
                 }
-                // insert markers
-                for marker in &state.config.markers {
-                    let (row, start, end) = marker;
-                    if row == &pos {
-                        let mut chars = Vec::new();
-                        for (i, char) in line.chars().enumerate() {
-                            if &i == start {
-                                chars.push(Codes::BACKGROUND_MARKER);
-                            }
-                            if &i == end {
-                                chars.push(Codes::RESET_BACKGROUND);
-                            }
-                            chars.push(char);
-                        }
-                        line = chars.iter().collect();
+                ranges.extend(state.markers_at(pos));
+                if let Some(selection) = &state.selection {
+                    if let Some((start, end)) = selection.range_on(pos, line.chars().count()) {
+                        ranges.push((start, end, Codes::BACKGROUND_SELECTION));
                     }
                 }
+                let ranges = ranges
+                    .into_iter()
+                    .map(|(start, end, code)| {
+                        let (start, end) = state.book.snap(pos, (start, end));
+                        (start, end, code)
+                    })
+                    .collect::<Vec<_>>();
+                line = insert_ranges(&line, &ranges);
                 // insert formatting codes
                 let mut slices = Vec::new();
                 for char in line.chars() {
                     match char {
-                        Codes::RESET => slices.push("\x1b[0m".to_string()),
+                        Codes::RESET => slices.push(reset.clone()),
+                        Codes::BOLD => slices.push("\x1b[1m".to_string()),
+                        Codes::RESET_BOLD => slices.push("\x1b[22m".to_string()),
                         Codes::ITALIC => slices.push("\x1b[3m".to_string()),
                         Codes::RESET_ITALIC => slices.push("\x1b[23m".to_string()),
                         Codes::UNDERLINE => slices.push("\x1b[4m".to_string()),
                         Codes::RESET_UNDERLINE => slices.push("\x1b[24m".to_string()),
-                        Codes::BACKGROUND_MARKER => slices.push("\x1b[48;2;90;90;0m".to_string()),
+                        Codes::SOFT_BREAK => {}
+                        Codes::BACKGROUND_MARKER
+                        | Codes::BACKGROUND_MARKER_GREEN
+                        | Codes::BACKGROUND_MARKER_BLUE
+                        | Codes::BACKGROUND_MARKER_PINK => {
+                            slices.push(marker_background(char, theme))
+                        }
                         Codes::BACKGROUND_SELECTION => {
-                            slices.push("\x1b[48;2;100;100;100m".to_string());
-                            slices.push("\x1b[38;2;240;240;240m".to_string())
+                            slices.push(theme.highlight(theme.selection, theme.selection_text))
+                        }
+                        Codes::BACKGROUND_SEARCH => {
+                            slices.push(theme.highlight(theme.search, theme.search_text))
                         }
                         Codes::RESET_BACKGROUND => {
-                            slices.push("\x1b[49m".to_string());
-                            slices.push(line_color.to_string())
+                            slices.push(theme.reset_background());
+                            slices.push(line_color.clone())
                         }
                         _ => slices.push(char.to_string()),
                     }
                 }
                 line = slices.join("");
                 // render definition
-                if let Some(definition) = &state.definition {
-                    let ((row, _, _), definition) = definition;
-                    if row + 1 == pos {
+                if let Some((row, lines)) = &definition {
+                    if row + 1 == pos || row + 2 + lines.len() == pos {
                         line = "".to_string();
-                        line_color = "\x1b[38;2;240;240;240m";
+                        line_color = theme.fg(theme.text);
                     }
-                    if row + 1 < pos && row + 1 + definition.list.len() >= pos {
-                        let index = pos.saturating_sub(row + 2);
-                        if let Some(item) = definition.list.get(index) {
-                            line = format!("\x1b[38;2;160;160;160m  {}. {item}\x1b[0m", index + 1);
-                            line_color = "\x1b[38;2;240;240;240m";
-                        }
-                    }
-                    if row + 1 + definition.list.len() + 1 == pos {
-                        line = "".to_string();
-                        line_color = "\x1b[38;2;240;240;240m";
+                    if row + 1 < pos && row + 2 + lines.len() > pos {
+                        line = lines[pos - row - 2].to_string();
+                        line_color = theme.fg(theme.text);
                     }
                 }
                 // render message
@@ -291,24 +633,23 @@ fn render(term: &mut Terminal<Stdout>, state: &State) -> anyhow::Result<()> {
                         line = format!(
                             "{:-<line_width$}",
                             "",
-                            line_width = state.book.line_width.saturating_sub(10)
+                            line_width = state.book.line_width.saturating_sub(GUTTER_WIDTH)
                         );
-                        line_color = "\x1b[38;2;160;160;160m";
+                        line_color = theme.fg(theme.message_separator);
                     }
                     if i == state.screen_height.saturating_sub(1) {
                         line = message.to_string();
-                        line_color = "\x1b[38;2;240;240;240m";
+                        line_color = theme.fg(theme.message);
                     }
                 }
-                term.flush_batch()?;
                 term.write_all(
                     format!(
-                        "{: >pad_left$}{}{: >5} {}\x1b[0m {}{line}\x1b[0m",
+                        "{: >pad_left$}{}{: >5} {}{reset} {}{line}{reset}",
                         "",
                         if i == OFFSET {
-                            "\x1b[38;2;200;200;0m"
+                            theme.emphasis(theme.current_line_number)
                         } else {
-                            "\x1b[38;2;130;130;130m"
+                            theme.fg(theme.line_number)
                         },
                         if line_number % 5 == 0 || i == OFFSET {
                             line_number.to_string()
@@ -316,9 +657,9 @@ fn render(term: &mut Terminal<Stdout>, state: &State) -> anyhow::Result<()> {
                             String::default()
                         },
                         if is_bookmarked {
-                            "\x1b[38;2;240;240;240m>>>\x1b[0m"
+                            format!("{}>>>", theme.fg(theme.bookmark))
                         } else {
-                            "   "
+                            "   ".to_string()
                         },
                         line_color,
                         pad_left = state.pad_left,
@@ -328,10 +669,290 @@ fn render(term: &mut Terminal<Stdout>, state: &State) -> anyhow::Result<()> {
             }
         }
     }
+    // render current chapter
+    if let Some(chapter) = state.current_chapter() {
+        clear_line(term, theme, 0)?;
+        term.write_all(
+            format!(
+                "{: >pad_left$}{}{chapter}{reset}",
+                "",
+                theme.fg(theme.line_number),
+                pad_left = state.pad_left + GUTTER_WIDTH,
+            )
+            .as_bytes(),
+        )?;
+    }
+    term.flush()?;
+    Ok(())
+}
+
+fn render_overlay(
+    term: &mut Terminal<Stdout>,
+    state: &State,
+    overlay: Overlay,
+) -> anyhow::Result<()> {
+    let theme = &state.theme;
+    let reset = theme.reset();
+    let (title, rows, selected) = match overlay {
+        Overlay::Contents(index) | Overlay::Markers(index) | Overlay::Vocabulary(index) => {
+            let rows = state
+                .overlay_entries()
+                .into_iter()
+                .enumerate()
+                .map(|(i, (label, line_number))| {
+                    let color = match (overlay, state.config.markers.get(i)) {
+                        _ if i == index => theme.highlight(theme.selection, theme.selection_text),
+                        (Overlay::Markers(_), Some(marker)) => format!(
+                            "{}{}",
+                            marker_background(marker.color.code(), theme),
+                            theme.fg(theme.text)
+                        ),
+                        _ => theme.fg(theme.faded),
+                    };
+                    (Some(line_number), format!("{color}{label}"))
+                })
+                .collect::<Vec<_>>();
+            let title = match overlay {
+                Overlay::Contents(_) => "Contents",
+                Overlay::Vocabulary(_) => "Vocabulary",
+                _ => "Highlights",
+            };
+            (title, rows, index)
+        }
+        Overlay::Note(_) => {
+            let mut rows = Vec::new();
+            let text_width = state.book.line_width.saturating_sub(GUTTER_WIDTH);
+            if let Some(marker) = state.overlay_marker() {
+                let text = state.book.text_at(marker.anchor.offset, marker.length);
+                for line in wrap_text(&text, text_width) {
+                    rows.push((None, format!("\x1b[3m{}{line}", theme.fg(theme.faded))));
+                }
+                rows.push((None, String::new()));
+                match &marker.note {
+                    Some(note) => {
+                        for line in wrap_text(note, text_width) {
+                            rows.push((None, format!("{}{line}", theme.fg(theme.text))));
+                        }
+                    }
+                    None => rows.push((None, format!("{}(no note)", theme.fg(theme.dimmed)))),
+                }
+                rows.push((None, String::new()));
+                let hints = [
+                    (Action::EditEntry, "edit note"),
+                    (Action::RemoveEntry, "remove highlight"),
+                    (Action::Cancel, "close"),
+                ]
+                .iter()
+                .filter_map(|(action, hint)| {
+                    let keys = state.settings.keymap.keys_for(*action)?;
+                    Some(format!("{keys} {hint}"))
+                })
+                .collect::<Vec<_>>();
+                rows.push((
+                    None,
+                    format!("{}{}", theme.fg(theme.dimmed), hints.join(", ")),
+                ));
+            }
+            ("Note", rows, 0)
+        }
+    };
+    for i in 0..state.screen_height {
+        clear_line(term, theme, i)?;
+        if i + 2 == OFFSET {
+            term.write_all(
+                format!(
+                    "{: >pad_left$}{}\x1b[1m{title}{reset}",
+                    "",
+                    theme.fg(theme.text),
+                    pad_left = state.pad_left + GUTTER_WIDTH,
+                )
+                .as_bytes(),
+            )?;
+        }
+        if selected + i < OFFSET {
+            continue;
+        }
+        let index = selected + i - OFFSET;
+        if let Some((line_number, row)) = rows.get(index) {
+            term.write_all(
+                format!(
+                    "{: >pad_left$}{}{: >5}    {row}{reset}",
+                    "",
+                    if index == selected {
+                        theme.emphasis(theme.current_line_number)
+                    } else {
+                        theme.fg(theme.line_number)
+                    },
+                    line_number.map(|line| line.to_string()).unwrap_or_default(),
+                    pad_left = state.pad_left,
+                )
+                .as_bytes(),
+            )?;
+        }
+    }
+    render_message(term, state)?;
     term.flush()?;
     Ok(())
 }
 
+fn render_message(term: &mut Terminal<Stdout>, state: &State) -> anyhow::Result<()> {
+    if let Some(message) = &state.message {
+        let theme = &state.theme;
+        clear_line(term, theme, state.screen_height.saturating_sub(2))?;
+        clear_line(term, theme, state.screen_height.saturating_sub(1))?;
+        term.act(TermAction::MoveCursorTo(
+            0,
+            state.screen_height.saturating_sub(2) as u16,
+        ))?;
+        term.write_all(
+            format!(
+                "{: >pad_left$}{}{:-<line_width$}{reset}\r\n{: >pad_left$}{}{message}{reset}",
+                "",
+                theme.fg(theme.message_separator),
+                "",
+                "",
+                theme.fg(theme.message),
+                reset = theme.reset(),
+                line_width = state.book.line_width.saturating_sub(GUTTER_WIDTH),
+                pad_left = state.pad_left + GUTTER_WIDTH,
+            )
+            .as_bytes(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Lays out a definition below the selection, grouping the senses by part of speech.
+fn definition_lines(definition: &Definition, width: usize, theme: &Theme) -> Vec<String> {
+    let mut lines = Vec::new();
+    let reset = theme.reset();
+    let mut heading = format!("{}\x1b[1m{}\x1b[22m", theme.fg(theme.text), definition.word);
+    if !definition.phonetics.is_empty() {
+        heading.push_str(&format!(
+            "  {}{}{reset}",
+            theme.fg(theme.faded),
+            definition.phonetics.join(", ")
+        ));
+    }
+    lines.push(heading);
+    let mut push_wrapped = |text: &str, first: &str, indent: usize, style: &str| {
+        let rest = " ".repeat(indent);
+        let width = width.saturating_sub(indent).max(1);
+        for (i, part) in wrap_text(text, width).iter().enumerate() {
+            let prefix = if i == 0 { first } else { &rest };
+            lines.push(format!("{prefix}{style}{part}{reset}"));
+        }
+    };
+    let italic = |color: Color| format!("\x1b[3m{}", theme.fg(color));
+    for meaning in &definition.meanings {
+        if let Some(part_of_speech) = &meaning.part_of_speech {
+            push_wrapped(part_of_speech, "", 0, &italic(theme.accent));
+        }
+        for (i, sense) in meaning.senses.iter().enumerate() {
+            let number = format!("  {}. ", i + 1);
+            let indent = number.len();
+            push_wrapped(&sense.definition, &number, indent, &theme.fg(theme.text));
+            let rest = " ".repeat(indent);
+            if let Some(example) = &sense.example {
+                let example = format!("\"{example}\"");
+                push_wrapped(&example, &rest, indent, &italic(theme.line_number));
+            }
+            if !sense.synonyms.is_empty() {
+                let synonyms = format!("synonyms: {}", sense.synonyms.join(", "));
+                push_wrapped(&synonyms, &rest, indent, &theme.fg(theme.line_number));
+            }
+            if !sense.antonyms.is_empty() {
+                let antonyms = format!("antonyms: {}", sense.antonyms.join(", "));
+                push_wrapped(&antonyms, &rest, indent, &theme.fg(theme.line_number));
+            }
+        }
+        if !meaning.synonyms.is_empty() {
+            let synonyms = format!("synonyms: {}", meaning.synonyms.join(", "));
+            push_wrapped(&synonyms, "  ", 2, &theme.fg(theme.faded));
+        }
+        if !meaning.antonyms.is_empty() {
+            let antonyms = format!("antonyms: {}", meaning.antonyms.join(", "));
+            push_wrapped(&antonyms, "  ", 2, &theme.fg(theme.faded));
+        }
+    }
+    lines
+}
+
+fn marker_background(code: char, theme: &Theme) -> String {
+    match code {
+        Codes::BACKGROUND_MARKER_GREEN => theme.marker(theme.marker_green),
+        Codes::BACKGROUND_MARKER_BLUE => theme.marker(theme.marker_blue),
+        Codes::BACKGROUND_MARKER_PINK => theme.marker(theme.marker_pink),
+        _ => theme.marker(theme.marker_yellow),
+    }
+}
+
+/// Clears a row of the screen, painting it with the background of the theme.
+fn clear_line(term: &mut Terminal<Stdout>, theme: &Theme, row: usize) -> anyhow::Result<()> {
+    term.act(TermAction::MoveCursorTo(0, row as u16))?;
+    term.write_all(theme.reset().as_bytes())?;
+    term.batch(TermAction::ClearTerminal(Clear::CurrentLine))?;
+    term.flush_batch()?;
+    Ok(())
+}
+
+fn edit_note(term: &mut Terminal<Stdout>, state: &mut State) -> anyhow::Result<()> {
+    let note = state
+        .overlay_marker()
+        .and_then(|marker| marker.note.clone())
+        .unwrap_or_default();
+    match read_input(term, state, "Note: ", &note, |_, _| ())? {
+        Some(note) => state.set_note(&note)?,
+        None => state.clear_message(),
+    }
+    Ok(())
+}
+
+fn insert_ranges(line: &str, ranges: &[(usize, usize, char)]) -> String {
+    let mut chars = Vec::new();
+    for (i, char) in line.chars().enumerate() {
+        for (start, end, code) in ranges {
+            if &i == start {
+                chars.push(*code);
+            }
+            if &i == end {
+                chars.push(Codes::RESET_BACKGROUND);
+            }
+        }
+        chars.push(char);
+    }
+    chars.iter().collect()
+}
+
+/// Reads a line of input into the message bar, calling `on_change` after every edit.
+/// Returns `None` if the input was cancelled with Esc.
+fn read_input(
+    term: &mut Terminal<Stdout>,
+    state: &mut State,
+    prompt: &str,
+    initial: &str,
+    mut on_change: impl FnMut(&mut State, &str),
+) -> anyhow::Result<Option<String>> {
+    let mut input = initial.to_string();
+    loop {
+        state.show_message(&format!("{prompt}{input}"));
+        render(term, state)?;
+        state.update_screen = false;
+        if let Some(key) = read_key(term)? {
+            match key.code {
+                KeyCode::Enter => return Ok(Some(input)),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(char) => input.push(char),
+                _ => continue,
+            }
+            on_change(state, &input);
+        }
+    }
+}
+
 fn read_key(term: &mut Terminal<Stdout>) -> anyhow::Result<Option<KeyEvent>> {
     if let Retrieved::Event(Some(Event::Key(key))) = term.get(Value::Event(None))? {
         return Ok(Some(key));
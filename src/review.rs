@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+pub const DEFAULT_EASE: f64 = 2.5;
+pub const MIN_EASE: f64 = 1.3;
+pub const DAY: u64 = 24 * 60 * 60;
+
+/// The spaced repetition schedule of a vocabulary entry, following the SM-2 algorithm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Review {
+    pub repetitions: u32,
+    /// Days until the next review.
+    pub interval: u64,
+    pub ease: f64,
+    pub due: u64,
+    /// Every grade given so far, from 0 (forgotten) to 5 (perfect recall).
+    pub grades: Vec<u8>,
+}
+
+impl Default for Review {
+    fn default() -> Self {
+        Self {
+            repetitions: 0,
+            interval: 0,
+            ease: DEFAULT_EASE,
+            due: 0,
+            grades: Vec::new(),
+        }
+    }
+}
+
+impl Review {
+    pub fn is_due(&self, now: u64) -> bool {
+        self.due <= now
+    }
+
+    /// Schedules the next review after recalling a word with the given quality from 0 to 5.
+    /// Words graded below 3 start over with daily reviews and keep their ease.
+    pub fn grade(&mut self, quality: u8, now: u64) {
+        let quality = quality.min(5);
+        if quality >= 3 {
+            self.interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval as f64 * self.ease).round() as u64,
+            };
+            self.repetitions += 1;
+            let penalty = (5 - quality) as f64;
+            self.ease = (self.ease + 0.1 - penalty * (0.08 + penalty * 0.02)).max(MIN_EASE);
+        } else {
+            self.repetitions = 0;
+            self.interval = 1;
+        }
+        self.due = now + self.interval * DAY;
+        self.grades.push(quality);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_recall_keeps_ease() {
+        let mut review = Review::default();
+        review.grade(5, 0);
+        review.grade(4, 0);
+        let ease = review.ease;
+        review.grade(1, 0);
+        assert_eq!(review.ease, ease);
+        assert_eq!(review.repetitions, 0);
+        assert_eq!(review.interval, 1);
+        review.grade(3, 0);
+        assert!(review.ease < ease);
+    }
+}
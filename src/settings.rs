@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::color::ColorDepth;
+use crate::dictionary::DictionarySource;
+use crate::keymap::Action;
+use crate::keymap::Keymap;
+use crate::theme::Theme;
+use crate::theme::BUILTIN_THEMES;
+use crate::xdg_dir;
+
+/// Preferences shared by all books, read from `$XDG_CONFIG_HOME/booklet/config.toml`. The
+/// state of a book can override the theme, line width, focus mode and dictionary.
+///
+/// ```toml
+/// theme = "sepia"
+/// line_width = 70
+/// focus_mode = true
+///
+/// [dictionary]
+/// provider = "stardict"
+/// path = "/usr/share/stardict/dic/wordnet"
+///
+/// [keys]
+/// move_down = ["j", "<Down>", "<C-n>"]
+/// goto_top = ["gg", "<Home>"]
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The name of the theme used unless a book chose another one.
+    pub theme: Option<String>,
+    pub line_width: Option<usize>,
+    pub focus_mode: Option<bool>,
+    pub dictionary: Option<DictionarySource>,
+    /// A command the copied text is piped into, for terminals without OSC 52 support.
+    pub clipboard: Option<String>,
+    /// Stores the state of a book in a hidden file next to it instead of the data directory.
+    pub sidecar: bool,
+    /// Themes defined in addition to the built-in ones, which they can also replace.
+    pub themes: BTreeMap<String, Theme>,
+    /// Overrides the detected color depth: `truecolor`, `256`, `16` or `monochrome`.
+    pub colors: Option<ColorDepth>,
+    /// Key sequences replacing the default ones of an action.
+    pub keys: BTreeMap<Action, Vec<String>>,
+    /// The default keys together with `keys`, checked for conflicts when loading.
+    #[serde(skip)]
+    pub keymap: Keymap,
+}
+
+impl Settings {
+    pub fn path() -> Option<PathBuf> {
+        let mut path = xdg_dir("XDG_CONFIG_HOME", ".config")?;
+        path.push("booklet");
+        path.push("config.toml");
+        Some(path)
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        match Self::path() {
+            Some(path) if path.exists() => {
+                let content = fs::read_to_string(&path)?;
+                let invalid = |err| anyhow::anyhow!("Invalid config {}: {err}", path.display());
+                let mut settings =
+                    toml::from_str::<Self>(&content).map_err(|err| invalid(err.to_string()))?;
+                settings.keymap =
+                    Keymap::new(&settings.keys).map_err(|err| invalid(err.to_string()))?;
+                Ok(settings)
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn color_depth(&self) -> ColorDepth {
+        self.colors.unwrap_or_else(ColorDepth::detect)
+    }
+
+    /// Returns a theme defined in the config or a built-in one by name, set up for the color
+    /// depth of the terminal.
+    pub fn theme(&self, name: &str) -> Option<Theme> {
+        let mut theme = self
+            .themes
+            .get(name)
+            .cloned()
+            .or_else(|| Theme::builtin(name))?;
+        theme.depth = self.color_depth();
+        Some(theme)
+    }
+
+    /// Returns the theme with the given name, falling back to the configured theme and then to
+    /// the dark theme.
+    pub fn theme_or_default(&self, name: Option<&str>) -> Theme {
+        let mut theme = name
+            .and_then(|name| self.theme(name))
+            .or_else(|| self.theme.as_deref().and_then(|name| self.theme(name)))
+            .unwrap_or_default();
+        theme.depth = self.color_depth();
+        theme
+    }
+
+    /// Returns the name of the theme used for books which did not choose one.
+    pub fn default_theme_name(&self) -> String {
+        self.theme
+            .clone()
+            .filter(|name| self.theme(name).is_some())
+            .unwrap_or_else(|| BUILTIN_THEMES[0].to_string())
+    }
+
+    /// Returns the names of all themes, the built-in ones first.
+    pub fn theme_names(&self) -> Vec<String> {
+        let mut names = BUILTIN_THEMES.map(String::from).to_vec();
+        for name in self.themes.keys() {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_book_theme_falls_back_to_settings_theme() {
+        let settings = Settings {
+            theme: Some("light".to_string()),
+            ..Default::default()
+        };
+        let theme = settings.theme_or_default(Some("removed"));
+        assert_eq!(theme.text, Theme::light().text);
+        let settings = Settings::default();
+        let theme = settings.theme_or_default(Some("removed"));
+        assert_eq!(theme.text, Theme::dark().text);
+    }
+}
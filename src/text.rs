@@ -0,0 +1,169 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::Codes;
+
+/// A user-perceived character of a stored line: a base char together with the combining
+/// marks, variation selectors and joined chars displayed with it. `start..end` are char
+/// indexes into the line, `column` is where the grapheme is displayed and `width` the number
+/// of columns it takes up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grapheme {
+    pub start: usize,
+    pub end: usize,
+    pub column: usize,
+    pub width: usize,
+}
+
+/// Closing punctuation which must not start a line of text written without spaces.
+const NO_BREAK_BEFORE: &str =
+    "、。，．・：；？！）」』】〕〉》〙〗｝］”’ーぁぃぅぇぉっゃゅょゎァィゥェォッャュョヮヵヶ";
+
+/// Splits a stored line into graphemes, skipping formatting codes.
+pub fn graphemes(line: &str) -> Vec<Grapheme> {
+    // the chars of the line without formatting codes, with their indexes into the line
+    let (indexes, text): (Vec<usize>, String) = line
+        .chars()
+        .enumerate()
+        .filter(|(_, char)| !Codes::is_code(*char))
+        .unzip();
+    let mut graphemes = Vec::new();
+    let mut column = 0;
+    let mut start = 0;
+    for cluster in text.graphemes(true) {
+        let count = cluster.chars().count();
+        let width = cluster.width();
+        graphemes.push(Grapheme {
+            start: indexes[start],
+            end: indexes[start + count - 1] + 1,
+            column,
+            width,
+        });
+        column += width;
+        start += count;
+    }
+    graphemes
+}
+
+/// Returns the number of columns a stored line takes up on screen.
+pub fn width(line: &str) -> usize {
+    graphemes(line)
+        .last()
+        .map(|grapheme| grapheme.column + grapheme.width)
+        .unwrap_or_default()
+}
+
+/// Returns the char range of the grapheme containing a char index, or of the first grapheme
+/// after it if the index points at a formatting code.
+pub fn grapheme_at(line: &str, index: usize) -> Option<(usize, usize)> {
+    graphemes(line)
+        .into_iter()
+        .find(|grapheme| grapheme.end > index)
+        .map(|grapheme| (grapheme.start, grapheme.end))
+}
+
+/// Returns the column a char index of a stored line is displayed at.
+pub fn column_at(line: &str, index: usize) -> usize {
+    let graphemes = graphemes(line);
+    match graphemes.iter().find(|grapheme| grapheme.end > index) {
+        Some(grapheme) => grapheme.column,
+        None => graphemes
+            .last()
+            .map(|grapheme| grapheme.column + grapheme.width)
+            .unwrap_or_default(),
+    }
+}
+
+/// Returns the char index of the grapheme displayed at a column of a stored line. Both columns
+/// of a wide grapheme map to it.
+pub fn index_at(line: &str, column: usize) -> Option<usize> {
+    graphemes(line)
+        .into_iter()
+        .find(|grapheme| (grapheme.column..grapheme.column + grapheme.width).contains(&column))
+        .map(|grapheme| grapheme.start)
+}
+
+/// Widens a range of char indexes to whole graphemes.
+pub fn snap(line: &str, (start, end): (usize, usize)) -> (usize, usize) {
+    let graphemes = graphemes(line);
+    let start = graphemes
+        .iter()
+        .find(|grapheme| grapheme.end > start)
+        .map_or(start, |grapheme| grapheme.start.min(start));
+    let end = graphemes
+        .iter()
+        .find(|grapheme| grapheme.end >= end)
+        .filter(|grapheme| grapheme.start < end)
+        .map_or(end, |grapheme| grapheme.end);
+    (start, end)
+}
+
+/// Splits a word so that the first part fits into `width` columns. Text written without spaces
+/// is split next to a wide char, other words only if `anywhere` is set. Returns `None` if no
+/// part of the word fits.
+pub fn split(word: &str, width: usize, anywhere: bool) -> Option<(String, String)> {
+    let graphemes = graphemes(word);
+    let chars = word.chars().collect::<Vec<_>>();
+    let mut split = None;
+    for pair in graphemes.windows(2) {
+        let (before, after) = (pair[0], pair[1]);
+        if after.column > width {
+            break;
+        }
+        let breakable = anywhere || before.width > 1 || after.width > 1;
+        if breakable && !NO_BREAK_BEFORE.contains(chars[after.start]) {
+            split = Some(after.start);
+        }
+    }
+    let index = split?;
+    Some((
+        chars[..index].iter().collect(),
+        chars[index..].iter().collect(),
+    ))
+}
+
+/// Returns whether a line contains wide chars, as text written without spaces does.
+pub fn is_wide(line: &str) -> bool {
+    graphemes(line).iter().any(|grapheme| grapheme.width > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clusters(line: &str) -> Vec<(usize, usize, usize)> {
+        graphemes(line)
+            .iter()
+            .map(|grapheme| (grapheme.start, grapheme.end, grapheme.width))
+            .collect()
+    }
+
+    #[test]
+    fn graphemes_follow_unicode_segmentation() {
+        // an emoji sequence joined by zero width joiners
+        assert_eq!(
+            clusters("👨\u{200D}👩\u{200D}👧!"),
+            vec![(0, 5, 2), (5, 6, 1)]
+        );
+        // pairs of regional indicators
+        assert_eq!(clusters("🇩🇪🇫🇷"), vec![(0, 2, 2), (2, 4, 2)]);
+        // a Hangul syllable written as conjoining jamo
+        assert_eq!(
+            clusters("\u{1100}\u{1161}\u{11A8}a"),
+            vec![(0, 3, 2), (3, 4, 1)]
+        );
+        assert_eq!(clusters("e\u{301}\u{E001}x"), vec![(0, 2, 1), (3, 4, 1)]);
+    }
+
+    #[test]
+    fn graphemes_have_display_width() {
+        assert_eq!(clusters("漢a"), vec![(0, 1, 2), (1, 2, 1)]);
+        // U+FE0F asks for the emoji presentation of the char before it
+        assert_eq!(clusters("\u{263A}\u{FE0F}"), vec![(0, 2, 2)]);
+        assert_eq!(clusters("\u{263A}"), vec![(0, 1, 1)]);
+        assert_eq!(
+            clusters("a\u{200B}b"),
+            vec![(0, 1, 1), (1, 2, 0), (2, 3, 1)]
+        );
+    }
+}
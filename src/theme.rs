@@ -0,0 +1,258 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::color;
+use crate::color::ColorDepth;
+
+pub const BUILTIN_THEMES: [&str; 4] = ["dark", "light", "sepia", "solarized"];
+
+/// A 24-bit color, written as `#rrggbb` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl Color {
+    /// Returns the escape sequence setting the foreground color, downsampled to the depth.
+    pub fn fg(self, depth: ColorDepth) -> String {
+        match depth {
+            ColorDepth::TrueColor => format!("\x1b[38;2;{};{};{}m", self.0, self.1, self.2),
+            ColorDepth::Ansi256 => format!("\x1b[38;5;{}m", color::ansi256(self)),
+            ColorDepth::Ansi16 => match color::ansi16(self) {
+                (index, false) => format!("\x1b[{}m", 30 + index),
+                (index, true) => format!("\x1b[{}m", 90 + index),
+            },
+            ColorDepth::Monochrome => String::new(),
+        }
+    }
+
+    /// Returns the escape sequence setting the background color, downsampled to the depth.
+    pub fn bg(self, depth: ColorDepth) -> String {
+        match depth {
+            ColorDepth::TrueColor => format!("\x1b[48;2;{};{};{}m", self.0, self.1, self.2),
+            ColorDepth::Ansi256 => format!("\x1b[48;5;{}m", color::ansi256(self)),
+            ColorDepth::Ansi16 => match color::ansi16(self) {
+                (index, false) => format!("\x1b[{}m", 40 + index),
+                (index, true) => format!("\x1b[{}m", 100 + index),
+            },
+            ColorDepth::Monochrome => String::new(),
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> anyhow::Result<Self> {
+        let hex = value.strip_prefix('#').unwrap_or(&value);
+        if hex.len() != 6 || !hex.chars().all(|char| char.is_ascii_hexdigit()) {
+            anyhow::bail!("Invalid color '{value}', expected #rrggbb");
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        Ok(Color(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+    }
+}
+
+/// The colors the reader is drawn with. Themes defined in the config only need to list the
+/// colors they change, the rest are taken from the dark theme.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Painted behind the whole screen, or the terminal's own background if not set.
+    pub background: Option<Color>,
+    pub text: Color,
+    /// Lines next to the focus line in focus mode, quotes and other secondary text.
+    pub faded: Color,
+    /// Lines away from the focus line in focus mode, hints and other unimportant text.
+    pub dimmed: Color,
+    pub line_number: Color,
+    pub current_line_number: Color,
+    pub bookmark: Color,
+    /// Parts of speech in definitions.
+    pub accent: Color,
+    pub selection: Color,
+    pub selection_text: Color,
+    pub search: Color,
+    pub search_text: Color,
+    pub marker_yellow: Color,
+    pub marker_green: Color,
+    pub marker_blue: Color,
+    pub marker_pink: Color,
+    pub message: Color,
+    pub message_separator: Color,
+    /// The color depth of the terminal the theme is drawn on.
+    #[serde(skip)]
+    pub depth: ColorDepth,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    /// Returns one of the built-in themes by name.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "sepia" => Some(Self::sepia()),
+            "solarized" => Some(Self::solarized()),
+            _ => None,
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            background: None,
+            text: Color(240, 240, 240),
+            faded: Color(160, 160, 160),
+            dimmed: Color(100, 100, 100),
+            line_number: Color(130, 130, 130),
+            current_line_number: Color(200, 200, 0),
+            bookmark: Color(240, 240, 240),
+            accent: Color(200, 200, 0),
+            selection: Color(100, 100, 100),
+            selection_text: Color(240, 240, 240),
+            search: Color(40, 80, 120),
+            search_text: Color(240, 240, 240),
+            marker_yellow: Color(90, 90, 0),
+            marker_green: Color(30, 90, 30),
+            marker_blue: Color(30, 60, 110),
+            marker_pink: Color(110, 40, 80),
+            message: Color(240, 240, 240),
+            message_separator: Color(160, 160, 160),
+            depth: ColorDepth::default(),
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            background: None,
+            text: Color(30, 30, 30),
+            faded: Color(90, 90, 90),
+            dimmed: Color(170, 170, 170),
+            line_number: Color(140, 140, 140),
+            current_line_number: Color(160, 110, 0),
+            bookmark: Color(30, 30, 30),
+            accent: Color(160, 110, 0),
+            selection: Color(200, 200, 200),
+            selection_text: Color(0, 0, 0),
+            search: Color(170, 210, 240),
+            search_text: Color(0, 0, 0),
+            marker_yellow: Color(250, 240, 150),
+            marker_green: Color(190, 230, 180),
+            marker_blue: Color(180, 210, 240),
+            marker_pink: Color(240, 190, 215),
+            message: Color(30, 30, 30),
+            message_separator: Color(140, 140, 140),
+            depth: ColorDepth::default(),
+        }
+    }
+
+    pub fn sepia() -> Self {
+        Self {
+            background: Some(Color(244, 236, 216)),
+            text: Color(91, 70, 54),
+            faded: Color(130, 110, 90),
+            dimmed: Color(190, 175, 150),
+            line_number: Color(165, 145, 120),
+            current_line_number: Color(170, 90, 40),
+            bookmark: Color(91, 70, 54),
+            accent: Color(170, 90, 40),
+            selection: Color(215, 200, 170),
+            selection_text: Color(60, 40, 25),
+            search: Color(190, 210, 200),
+            search_text: Color(60, 40, 25),
+            marker_yellow: Color(240, 220, 140),
+            marker_green: Color(200, 220, 160),
+            marker_blue: Color(190, 210, 220),
+            marker_pink: Color(235, 195, 190),
+            message: Color(91, 70, 54),
+            message_separator: Color(165, 145, 120),
+            depth: ColorDepth::default(),
+        }
+    }
+
+    pub fn solarized() -> Self {
+        Self {
+            background: Some(Color(0, 43, 54)),
+            text: Color(147, 161, 161),
+            faded: Color(131, 148, 150),
+            dimmed: Color(88, 110, 117),
+            line_number: Color(88, 110, 117),
+            current_line_number: Color(181, 137, 0),
+            bookmark: Color(147, 161, 161),
+            accent: Color(181, 137, 0),
+            selection: Color(88, 110, 117),
+            selection_text: Color(253, 246, 227),
+            search: Color(38, 139, 210),
+            search_text: Color(253, 246, 227),
+            marker_yellow: Color(93, 80, 0),
+            marker_green: Color(50, 75, 0),
+            marker_blue: Color(10, 70, 110),
+            marker_pink: Color(100, 30, 70),
+            message: Color(147, 161, 161),
+            message_separator: Color(88, 110, 117),
+            depth: ColorDepth::default(),
+        }
+    }
+
+    pub fn fg(&self, color: Color) -> String {
+        color.fg(self.depth)
+    }
+
+    pub fn bg(&self, color: Color) -> String {
+        color.bg(self.depth)
+    }
+
+    /// Returns the escape sequence for text set off by a background, like the selection. It is
+    /// reversed in monochrome mode.
+    pub fn highlight(&self, background: Color, text: Color) -> String {
+        match self.depth {
+            ColorDepth::Monochrome => "\x1b[7m".to_string(),
+            _ => format!("{}{}", self.bg(background), self.fg(text)),
+        }
+    }
+
+    /// Returns the escape sequence for highlighted text, which is underlined in monochrome mode.
+    pub fn marker(&self, color: Color) -> String {
+        match self.depth {
+            ColorDepth::Monochrome => "\x1b[4m".to_string(),
+            _ => self.bg(color),
+        }
+    }
+
+    /// Returns the escape sequence for text which stands out, like the current line number. It
+    /// is bold in monochrome mode.
+    pub fn emphasis(&self, color: Color) -> String {
+        match self.depth {
+            ColorDepth::Monochrome => "\x1b[1m".to_string(),
+            _ => self.fg(color),
+        }
+    }
+
+    /// Returns the escape sequence resetting all attributes to the theme's defaults.
+    pub fn reset(&self) -> String {
+        match (self.depth, self.background) {
+            (ColorDepth::Monochrome, _) | (_, None) => "\x1b[0m".to_string(),
+            (_, Some(color)) => format!("\x1b[0m{}", self.bg(color)),
+        }
+    }
+
+    /// Returns the escape sequence resetting the background to the theme's background, or
+    /// ending a highlight in monochrome mode.
+    pub fn reset_background(&self) -> String {
+        match (self.depth, self.background) {
+            (ColorDepth::Monochrome, _) => "\x1b[24m\x1b[27m".to_string(),
+            (_, Some(color)) => self.bg(color),
+            (_, None) => "\x1b[49m".to_string(),
+        }
+    }
+}
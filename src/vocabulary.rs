@@ -0,0 +1,258 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::review::Review;
+use crate::xdg_dir;
+use crate::Anchor;
+use crate::Definition;
+
+/// Words looked up while reading, stored in `$XDG_DATA_HOME/booklet/vocabulary.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Vocabulary {
+    pub entries: Vec<VocabularyEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VocabularyEntry {
+    /// The form the definition was found for.
+    pub word: String,
+    /// The word as it appeared in the book.
+    pub selected: String,
+    pub sentence: String,
+    pub book: String,
+    pub line: usize,
+    pub anchor: Anchor,
+    pub definition: Definition,
+    pub added_at: u64,
+    #[serde(default)]
+    pub review: Review,
+}
+
+impl Vocabulary {
+    pub fn path() -> Option<PathBuf> {
+        let mut path = xdg_dir("XDG_DATA_HOME", ".local/share")?;
+        path.push("booklet");
+        path.push("vocabulary.json");
+        Some(path)
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    fn load_from(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => anyhow::bail!("No data directory found"),
+        };
+        self.save_to(&path)
+    }
+
+    fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Records a word unless it is already in the vocabulary. Returns whether it was added.
+    pub fn add(&mut self, entry: VocabularyEntry) -> bool {
+        if self
+            .entries
+            .iter()
+            .any(|other| other.word.to_lowercase() == entry.word.to_lowercase())
+        {
+            return false;
+        }
+        self.entries.push(entry);
+        true
+    }
+
+    /// Returns the indexes of the entries due for review, the longest overdue first.
+    pub fn due(&self, now: u64) -> Vec<usize> {
+        let mut due = (0..self.entries.len())
+            .filter(|&index| self.entries[index].review.is_due(now))
+            .collect::<Vec<_>>();
+        due.sort_by_key(|&index| self.entries[index].review.due);
+        due
+    }
+
+    /// Writes the vocabulary as Anki notes with the word and its context on the front and the
+    /// definitions on the back. Fields are separated by tabs, or by commas if `csv` is set.
+    pub fn to_anki(&self, csv: bool) -> String {
+        let mut content = String::new();
+        let separator = if csv { "comma" } else { "tab" };
+        writeln!(content, "#separator:{separator}").unwrap();
+        writeln!(content, "#html:true").unwrap();
+        for entry in &self.entries {
+            let fields = [front(entry), back(entry)];
+            let fields = fields.map(|field| match csv {
+                true => format!("\"{}\"", field.replace('"', "\"\"")),
+                false => field.replace(['\t', '\n'], " "),
+            });
+            writeln!(content, "{}", fields.join(if csv { "," } else { "\t" })).unwrap();
+        }
+        content
+    }
+}
+
+/// The word followed by the sentence it was read in, with the word underlined.
+fn front(entry: &VocabularyEntry) -> String {
+    let sentence = escape_html(&entry.sentence);
+    let selected = escape_html(&entry.selected);
+    let sentence = match sentence.find(&selected) {
+        Some(index) if !selected.is_empty() => format!(
+            "{}<u>{selected}</u>{}",
+            &sentence[..index],
+            &sentence[index + selected.len()..]
+        ),
+        _ => sentence,
+    };
+    format!(
+        "<b>{}</b><br><br><i>{sentence}</i>",
+        escape_html(&entry.word)
+    )
+}
+
+/// The definitions grouped by part of speech, followed by where the word was found.
+fn back(entry: &VocabularyEntry) -> String {
+    let definition = &entry.definition;
+    let mut back = String::new();
+    if !definition.phonetics.is_empty() {
+        write!(
+            back,
+            "{}<br>",
+            escape_html(&definition.phonetics.join(", "))
+        )
+        .unwrap();
+    }
+    for meaning in &definition.meanings {
+        if let Some(part_of_speech) = &meaning.part_of_speech {
+            write!(back, "<i>{}</i>", escape_html(part_of_speech)).unwrap();
+        }
+        back.push_str("<ol>");
+        for sense in &meaning.senses {
+            write!(back, "<li>{}", escape_html(&sense.definition)).unwrap();
+            if let Some(example) = &sense.example {
+                write!(back, "<br><i>\"{}\"</i>", escape_html(example)).unwrap();
+            }
+            back.push_str("</li>");
+        }
+        back.push_str("</ol>");
+        if !meaning.synonyms.is_empty() {
+            let synonyms = escape_html(&meaning.synonyms.join(", "));
+            write!(back, "synonyms: {synonyms}<br>").unwrap();
+        }
+    }
+    write!(
+        back,
+        "<small>{}, line {}</small>",
+        escape_html(&entry.book),
+        entry.line
+    )
+    .unwrap();
+    back
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use crate::dictionary::Meaning;
+    use crate::dictionary::Sense;
+
+    fn entry(word: &str, sentence: &str, definition: &str) -> VocabularyEntry {
+        VocabularyEntry {
+            word: word.to_string(),
+            selected: word.to_string(),
+            sentence: sentence.to_string(),
+            book: "Emma".to_string(),
+            line: 12,
+            anchor: Anchor {
+                offset: 40,
+                context: sentence.to_string(),
+            },
+            definition: Definition {
+                word: word.to_string(),
+                phonetics: Vec::new(),
+                meanings: vec![Meaning {
+                    part_of_speech: Some("noun".to_string()),
+                    senses: vec![Sense {
+                        definition: definition.to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+            },
+            added_at: 1_700_000_000,
+            review: Review::default(),
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_entries() {
+        let path = env::temp_dir()
+            .join(format!("booklet-vocabulary-{}", process::id()))
+            .join("vocabulary.json");
+        let mut vocabulary = Vocabulary::default();
+        assert!(vocabulary.add(entry("wit", "A ready wit.", "Humor.")));
+        assert!(!vocabulary.add(entry("Wit", "Another wit.", "Humor.")));
+        vocabulary.save_to(&path).unwrap();
+        let loaded = Vocabulary::load_from(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.entries.len(), 1);
+        let (saved, loaded) = (&vocabulary.entries[0], &loaded.entries[0]);
+        assert_eq!(loaded.word, "wit");
+        assert_eq!(loaded.sentence, saved.sentence);
+        assert_eq!(loaded.anchor, saved.anchor);
+        assert_eq!(loaded.added_at, saved.added_at);
+        assert_eq!(loaded.definition.meanings[0].senses[0].definition, "Humor.");
+    }
+
+    #[test]
+    fn exports_anki_notes_without_breaking_fields() {
+        let mut vocabulary = Vocabulary::default();
+        vocabulary.add(entry(
+            "wit",
+            "Her <b>wit</b>\tand\ncharm & \"grace\".",
+            "Quick\tand\ninventive <verbal> humor.",
+        ));
+        let tsv = vocabulary.to_anki(false);
+        let lines = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[..2], ["#separator:tab", "#html:true"]);
+        assert_eq!(lines.len(), 3);
+        let fields = lines[2].split('\t').collect::<Vec<_>>();
+        assert_eq!(
+            fields[0],
+            "<b>wit</b><br><br><i>Her &lt;b&gt;<u>wit</u>&lt;/b&gt; and charm &amp; \"grace\".</i>"
+        );
+        assert!(
+            fields[1].starts_with("<i>noun</i><ol><li>Quick and inventive &lt;verbal&gt; humor.")
+        );
+        let csv = vocabulary.to_anki(true);
+        assert!(csv.starts_with("#separator:comma\n"));
+        assert!(csv.contains("&amp; \"\"grace\"\".</i>\",\"<i>noun</i>"));
+        assert!(csv.contains("Quick\tand\ninventive"));
+    }
+}